@@ -17,6 +17,7 @@ in vec3     f_tangent_y;
 in vec2     f_texture_coordinates;
 flat in int f_material_id;
 flat in int f_random;
+in vec3     f_light;
 
 struct Material {
     int color_textures_count;
@@ -34,6 +35,8 @@ uniform vec2        u_atlas_size;
 uniform vec2        u_texture_size;
 uniform vec3        u_light_direction;
 uniform vec3        u_camera_pos;
+//Не 0 для блоков с вырезами (листва): прозрачные пиксели текстуры отбрасываются
+uniform int         u_alpha_test;
 
 
 const float PI = 3.14159265359;
@@ -42,6 +45,7 @@ const float PI = 3.14159265359;
 
 #[del]
 vec2 atlas_coords(vec2 texture_coords, int texture_id);
+vec4 atlas_texel(sampler2D atlas, vec2 texture_coords, int texture_id);
 int mod_positive(int a, int b);
 vec3 unit_vec(vec3 vec);
 #
@@ -49,12 +53,12 @@ vec3 unit_vec(vec3 vec);
 vec4 get_color() {
     int local_texture_id = mod_positive(f_random, u_materials[f_material_id].color_textures_count);
     int texture_id = u_materials[f_material_id].color_texture_id + local_texture_id;
-    return texture2D(u_texture_atlas, atlas_coords(f_texture_coordinates, texture_id));
+    return atlas_texel(u_texture_atlas, f_texture_coordinates, texture_id);
 }
 vec3 get_normal_texel() {
     int local_texture_id = mod_positive(f_random, u_materials[f_material_id].normal_textures_count);
     int texture_id = u_materials[f_material_id].normal_texture_id + local_texture_id;
-    vec3 normal = texture2D(u_texture_atlas, atlas_coords(f_texture_coordinates, texture_id)).xyz;
+    vec3 normal = atlas_texel(u_texture_atlas, f_texture_coordinates, texture_id).xyz;
 
     return unit_vec(normal * 2.0 - 1.0);
 }
@@ -62,7 +66,7 @@ vec3 get_light_texel() {
     int local_texture_id = mod_positive(f_random, u_materials[f_material_id].light_textures_count);
     int texture_id = u_materials[f_material_id].light_texture_id + local_texture_id;
 
-    return texture2D(u_texture_atlas, atlas_coords(f_texture_coordinates, texture_id)).xyz;
+    return atlas_texel(u_texture_atlas, f_texture_coordinates, texture_id).xyz;
 }
 
 void main() {
@@ -76,7 +80,11 @@ void main() {
 #endif
 
     vec4 color = get_color();
+    if (u_alpha_test != 0 && color.a < 0.5) discard;
     g_color = color.rgb * color.w;
 
-    g_light = get_light_texel();
+    //Светящиеся части материала (карта света) освещены не слабее своей яркости, как от блока-источника
+    vec3 light_texel = get_light_texel();
+    float glow = max(light_texel.r, max(light_texel.g, light_texel.b));
+    g_light = vec3(max(f_light.x, glow), f_light.yz);
 }
\ No newline at end of file
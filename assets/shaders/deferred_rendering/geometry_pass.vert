@@ -12,6 +12,7 @@ layout (location = 3) in vec3   v_tangent_y;
 layout (location = 4) in vec2   v_texture_coordinates;
 layout (location = 5) in int    v_material_id;
 layout (location = 6) in int    v_random;
+layout (location = 7) in vec3   v_light;   // Свет от блоков, свет неба, затенение углов
 
 out vec3 f_world_space_position;
 out vec3 f_light_space_position;
@@ -23,6 +24,7 @@ out vec3 f_tangent_y;
 out vec2 f_texture_coordinates;
 flat out int f_material_id;
 flat out int f_random;
+out vec3 f_light;
 
 
 uniform mat4 u_projview, u_model;
@@ -60,4 +62,5 @@ void main() {
     f_texture_coordinates = v_texture_coordinates;
     f_material_id = v_material_id;
     f_random = v_random;
+    f_light = v_light;
 }
\ No newline at end of file
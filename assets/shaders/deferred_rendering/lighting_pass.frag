@@ -10,18 +10,13 @@ uniform sampler2D g_light;
 
 uniform vec3 u_light_direction;
 uniform vec3 u_camera_pos;
+uniform mat4 u_projview;
 
-vec3 unit_vec(vec3 v) {
-    return v / length(v);
-}
+#include shading.frag
 
-float pow16_easy(float x) {
-    x *= x; //2
-    x *= x; //4
-    x *= x; //8
-    x *= x; //16
-    return x;
-}
+#[del]
+vec3 shade(vec3 color, vec3 position, vec3 normal, vec3 light);
+#
 
 void main() {
     vec3 f_world_space_position = texture2D(g_position, f_texture_coords).xyz;
@@ -33,17 +28,13 @@ void main() {
     if( length(f_normal) < 0.00001 ) {
         //Тут нужно просто нарисовать небо, позже этим займусь нормально
         out_color = vec4(0.55, 0.87, 0.95, 1.0);
+        gl_FragDepth = 1.0;
         return;
     }
 
-    float diffuse_light = max(0.0, -dot(f_normal,  u_light_direction));
-
-    vec3 local_light_ray = unit_vec(u_camera_pos - f_world_space_position);
-    vec3 reflected_light_ray = local_light_ray - 2.0 * f_normal * dot(local_light_ray, f_normal);
+    //Глубина из прохода геометрии, по ней полупрозрачные блоки прячутся за непрозрачными
+    vec4 clip_position = u_projview * vec4(f_world_space_position, 1.0);
+    gl_FragDepth = clip_position.z / clip_position.w * 0.5 + 0.5;
 
-    float specular = max(  dot(reflected_light_ray, u_light_direction), 0.0 );
-    specular = pow16_easy(specular);
-
-
-    out_color = vec4(f_color, 1.0) * (diffuse_light * 0.9 + 0.3) + 0.5 * specular;
-}
\ No newline at end of file
+    out_color = vec4(shade(f_color, f_world_space_position, f_normal, f_light), 1.0);
+}
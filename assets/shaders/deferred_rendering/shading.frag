@@ -0,0 +1,28 @@
+float pow16_easy(float x) {
+    x *= x; //2
+    x *= x; //4
+    x *= x; //8
+    x *= x; //16
+    return x;
+}
+
+//Освещенный цвет точки. light: x - свет от блоков, y - свет неба, z - затенение углов
+vec3 shade(vec3 color, vec3 position, vec3 normal, vec3 light) {
+    float diffuse_light = max(0.0, -dot(normal,  u_light_direction));
+
+    vec3 local_light_ray = normalize(u_camera_pos - position);
+    vec3 reflected_light_ray = local_light_ray - 2.0 * normal * dot(local_light_ray, normal);
+
+    float specular = max(  dot(reflected_light_ray, u_light_direction), 0.0 );
+    specular = pow16_easy(specular);
+
+    float block_light = light.x;
+    float sky_light   = light.y;
+    float ao          = light.z;
+
+    //Солнце светит только туда, куда доходит небо. Свет блоков теплый и не зависит от направления
+    vec3 ambient = vec3(0.3 * sky_light) + block_light * vec3(1.0, 0.85, 0.6);
+    vec3 lighting = vec3(diffuse_light * 0.9 * sky_light) + ambient * ao;
+
+    return color * lighting + 0.5 * specular * sky_light;
+}
@@ -0,0 +1,46 @@
+#version 330 core
+// Полупрозрачные блоки: рисуются после прохода освещения поверх готового изображения и освещаются сразу
+
+out vec4 out_color;
+
+in vec3     f_world_space_position;
+in vec3     f_normal;
+in vec2     f_texture_coordinates;
+flat in int f_material_id;
+flat in int f_random;
+in vec3     f_light;
+
+struct Material {
+    int color_textures_count;
+    int normal_textures_count;
+    int light_textures_count;
+
+    int color_texture_id;
+    int normal_texture_id;
+    int light_texture_id;
+};
+
+uniform Material    u_materials[50];
+uniform sampler2D   u_texture_atlas;
+uniform vec2        u_atlas_size;
+uniform vec2        u_texture_size;
+uniform vec3        u_light_direction;
+uniform vec3        u_camera_pos;
+
+#include utils.frag
+#include shading.frag
+
+#[del]
+vec2 atlas_coords(vec2 texture_coords, int texture_id);
+vec4 atlas_texel(sampler2D atlas, vec2 texture_coords, int texture_id);
+int mod_positive(int a, int b);
+vec3 shade(vec3 color, vec3 position, vec3 normal, vec3 light);
+#
+
+void main() {
+    int local_texture_id = mod_positive(f_random, u_materials[f_material_id].color_textures_count);
+    int texture_id = u_materials[f_material_id].color_texture_id + local_texture_id;
+    vec4 color = atlas_texel(u_texture_atlas, f_texture_coordinates, texture_id);
+
+    out_color = vec4(shade(color.rgb, f_world_space_position, normalize(f_normal), f_light), color.a);
+}
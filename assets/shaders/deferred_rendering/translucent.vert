@@ -0,0 +1,30 @@
+#version 330 core
+
+layout (location = 0) in vec3   v_position;
+layout (location = 1) in vec3   v_normal;
+layout (location = 4) in vec2   v_texture_coordinates;
+layout (location = 5) in int    v_material_id;
+layout (location = 6) in int    v_random;
+layout (location = 7) in vec3   v_light;   // Свет от блоков, свет неба, затенение углов
+
+out vec3 f_world_space_position;
+out vec3 f_normal;
+out vec2 f_texture_coordinates;
+flat out int f_material_id;
+flat out int f_random;
+out vec3 f_light;
+
+uniform mat4 u_projview, u_model;
+
+void main() {
+    vec4 world_pos = u_model * vec4(v_position, 1.0);
+    gl_Position = u_projview * world_pos;
+
+    f_world_space_position = world_pos.xyz / world_pos.w;
+    f_normal = (u_model * vec4(v_normal, 0.0)).xyz;
+
+    f_texture_coordinates = v_texture_coordinates;
+    f_material_id = v_material_id;
+    f_random = v_random;
+    f_light = v_light;
+}
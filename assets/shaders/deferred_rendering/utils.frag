@@ -2,7 +2,15 @@ vec2 atlas_coords(vec2 texture_coords, int texture_id) {
     //Количество текстур, помещающихся в атлас (по ширине и по высоте)
     ivec2 count = ivec2(floor(u_atlas_size / u_texture_size + 0.01));
     vec2 texture_pos = vec2(  float(texture_id % count.x), float(texture_id / count.y)  );
-    return (texture_pos + texture_coords) * u_texture_size / u_atlas_size;
+    //Координаты вне [0; 1] (объединенные стороны блоков) повторяют текстуру
+    return (texture_pos + fract(texture_coords)) * u_texture_size / u_atlas_size;
+}
+
+//Точка текстуры из атласа. Производные для выбора уровня mipmap берутся по координатам до повторения (fract),
+//иначе на каждом шве объединенной стороны они скачут и выбирается самый грубый уровень
+vec4 atlas_texel(sampler2D atlas, vec2 texture_coords, int texture_id) {
+    vec2 scale = u_texture_size / u_atlas_size;
+    return textureGrad(atlas, atlas_coords(texture_coords, texture_id), dFdx(texture_coords) * scale, dFdy(texture_coords) * scale);
 }
 
 int mod_positive(int a, int b) {
@@ -0,0 +1,171 @@
+use crate::game::{light_brightness, BlockId, Chunk, ChunkPos, Game, LightChannel, CHUNK_SIZE};
+use crate::mat::Vec3;
+
+/** Сторона куба окрестности: чанк и слой в один блок вокруг него */
+pub const HALO_SIZE: usize = CHUNK_SIZE + 2;
+
+/** Яркость вершины по количеству закрывающих ее блоков (0 - угол закрыт полностью, 3 - открыт) */
+pub const AO_LEVELS: [f32; 4] = [0.45, 0.65, 0.82, 1.0];
+
+/** Освещение вершины: свет от блоков, свет неба и затенение углов. Порядок совпадает с атрибутом вершины */
+pub type VertexLight = [f32; 3];
+/** Освещение вершины вне мира (предпросмотр блоков): полный свет неба, без затенения */
+pub const FULL_VERTEX_LIGHT: VertexLight = [0.0, 1.0, 1.0];
+
+/** Блоки, их непрозрачность и освещенность в чанке вместе с соседними блоками из других чанков.
+    Координаты локальные для чанка, от -1 до CHUNK_SIZE включительно */
+pub struct ChunkHalo {
+    blocks: Vec<BlockId>,   //Блоки незагруженных чанков - воздух
+    opaque: Vec<bool>,
+    light: Vec<Option<u8>>, //None - освещенность неизвестна (чанк не загружен)
+}
+impl ChunkHalo {
+    /** Все блоки прозрачные, освещенность неизвестна */
+    pub fn new() -> Self {
+        let volume = HALO_SIZE * HALO_SIZE * HALO_SIZE;
+        Self { blocks: vec![0; volume], opaque: vec![false; volume], light: vec![None; volume] }
+    }
+
+    fn id(x: i32, y: i32, z: i32) -> Option<usize> {
+        let range = -1..=CHUNK_SIZE as i32;
+        if !range.contains(&x) || !range.contains(&y) || !range.contains(&z) { return None; }
+        let (x, y, z) = ((x + 1) as usize, (y + 1) as usize, (z + 1) as usize);
+        Some(z * HALO_SIZE * HALO_SIZE + y * HALO_SIZE + x)
+    }
+
+    pub fn set(&mut self, x: i32, y: i32, z: i32, opaque: bool) {
+        if let Some(id) = Self::id(x, y, z) { self.opaque[id] = opaque; }
+    }
+    /** Непрозрачен ли блок. За пределами области все прозрачно */
+    pub fn is_opaque(&self, x: i32, y: i32, z: i32) -> bool {
+        Self::id(x, y, z).map(|id| self.opaque[id]).unwrap_or(false)
+    }
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: BlockId) {
+        if let Some(id) = Self::id(x, y, z) { self.blocks[id] = block; }
+    }
+    /** Блок в клетке. За пределами области - воздух */
+    pub fn block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        Self::id(x, y, z).map(|id| self.blocks[id]).unwrap_or(0)
+    }
+    pub fn set_light(&mut self, x: i32, y: i32, z: i32, light: u8) {
+        if let Some(id) = Self::id(x, y, z) { self.light[id] = Some(light); }
+    }
+    /** Освещенность блока (оба уровня, см. LightChannel). None, если неизвестна */
+    pub fn light(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+        Self::id(x, y, z).and_then(|id| self.light[id])
+    }
+
+    /** Освещение вершины блока x, y, z: свет от блоков и неба сглаживается по прозрачным клеткам у угла стороны.
+        Клетки с неизвестной освещенностью не учитываются */
+    pub fn vertex_light(&self, x: i32, y: i32, z: i32, pos: Vec3, normal: Vec3) -> VertexLight {
+        let ao = self.vertex_ao(x, y, z, pos, normal);
+        let (mut block, mut sky, mut count) = (0.0, 0.0, 0);
+        if let Some(corner) = CornerCells::new(x, y, z, pos, normal) {
+            let sides = [corner.side1, corner.side2];
+            //Через диагональ свет не проходит, если обе стороны закрыты
+            let diagonal = corner.diagonal.filter(|_| !sides.iter().all(|c| c.is_some_and(|c| self.is_opaque(c[0], c[1], c[2]))));
+            let cells = [Some(corner.front), corner.side1, corner.side2, diagonal];
+            for c in cells.iter().flatten() {
+                let light = match self.light(c[0], c[1], c[2]) {
+                    Some(light) if !self.is_opaque(c[0], c[1], c[2]) => light,
+                    _ => continue,
+                };
+                block += light_brightness(LightChannel::Block.get(light));
+                sky += light_brightness(LightChannel::Sky.get(light));
+                count += 1;
+            }
+        }
+        //Сторона закрыта или модель внутри клетки - свет самого блока
+        if count == 0 {
+            let light = self.light(x, y, z).unwrap_or(0);
+            return [light_brightness(LightChannel::Block.get(light)), light_brightness(LightChannel::Sky.get(light)), ao];
+        }
+        [block / count as f32, sky / count as f32, ao]
+    }
+
+    /** Затенение вершины блока x, y, z. pos - положение вершины относительно центра блока, normal - нормаль.
+        Смотрятся три блока у угла стороны: два сбоку и один по диагонали */
+    pub fn vertex_ao(&self, x: i32, y: i32, z: i32, pos: Vec3, normal: Vec3) -> f32 {
+        let corner = match CornerCells::new(x, y, z, pos, normal) {
+            Some(corner) => corner,
+            None => return 1.0,
+        };
+        let opaque = |c: Option<[i32; 3]>| c.is_some_and(|c| self.is_opaque(c[0], c[1], c[2]));
+        AO_LEVELS[ao_level(opaque(corner.side1), opaque(corner.side2), opaque(corner.diagonal))]
+    }
+}
+
+/** Клетки у угла стороны блока, к которому относится вершина */
+struct CornerCells {
+    /** Клетка перед стороной */
+    front: [i32; 3],
+    /** Соседи клетки front в сторону угла по двум другим осям и по диагонали.
+        None, если вершина не у края стороны по этой оси */
+    side1: Option<[i32; 3]>,
+    side2: Option<[i32; 3]>,
+    diagonal: Option<[i32; 3]>,
+}
+impl CornerCells {
+    /** None, если нормаль нулевая */
+    fn new(x: i32, y: i32, z: i32, pos: Vec3, normal: Vec3) -> Option<Self> {
+        let n = [normal.x(), normal.y(), normal.z()];
+        let p = [pos.x(), pos.y(), pos.z()];
+        let axis = (0..3).max_by(|a, b| n[*a].abs().total_cmp(&n[*b].abs())).unwrap();
+        if n[axis] == 0.0 { return None; }
+
+        //Клетка перед стороной и направления к углу по двум другим осям
+        let mut front = [x, y, z];
+        front[axis] += n[axis].signum() as i32;
+        let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+        let corner_dir = |i: usize| if p[i] > 0.25 { 1 } else if p[i] < -0.25 { -1 } else { 0 };
+        let (su, sv) = (corner_dir(u), corner_dir(v));
+
+        let cell = |du: i32, dv: i32| {
+            let mut c = front;
+            c[u] += du;
+            c[v] += dv;
+            c
+        };
+        Some(Self {
+            front,
+            side1: (su != 0).then(|| cell(su, 0)),
+            side2: (sv != 0).then(|| cell(0, sv)),
+            diagonal: (su != 0 && sv != 0).then(|| cell(su, sv)),
+        })
+    }
+}
+impl Default for ChunkHalo {
+    fn default() -> Self { Self::new() }
+}
+
+/** Уровень освещенности угла от 0 до 3. Если закрыты обе стороны, диагональный блок уже не важен */
+pub fn ao_level(side1: bool, side2: bool, corner: bool) -> usize {
+    if side1 && side2 { 0 } else { 3 - (side1 as usize + side2 as usize + corner as usize) }
+}
+
+impl Game {
+    /** Блоки, непрозрачность и освещенность чанка и его окрестности.
+        Блоки незагруженных чанков считаются прозрачными, их освещенность неизвестна */
+    pub fn chunk_halo(&self, pos: ChunkPos) -> ChunkHalo {
+        let chs = CHUNK_SIZE as i32;
+        let mut halo = ChunkHalo::new();
+        let chunk = self.chunk(pos);
+        for z in -1..=chs {
+            for y in -1..=chs {
+                for x in -1..=chs {
+                    let (wx, wy, wz) = (pos.0 * chs + x, pos.1 * chs + y, pos.2 * chs + z);
+                    let block = match chunk {
+                        Some(chunk) if Chunk::is_inside(x, y, z) => Some((chunk.block(x, y, z), chunk.state(x, y, z), chunk.light(x, y, z))),
+                        _ => self.block_at(wx, wy, wz).zip(self.light_at(wx, wy, wz)).map(|((block, state), light)| (block, state, light)),
+                    };
+                    if let Some((block, state, light)) = block {
+                        halo.set_block(x, y, z, block);
+                        halo.set(x, y, z, self.is_opaque(block, state));
+                        halo.set_light(x, y, z, light);
+                    }
+                }
+            }
+        }
+        halo
+    }
+}
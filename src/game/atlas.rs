@@ -267,11 +267,12 @@ fn nil_normal(w: u32, h: u32) -> DynamicImage {
     }
     image
 }
+/** Карта света по умолчанию - черная: материал сам не светится */
 fn nil_lightmap(w: u32, h: u32) -> DynamicImage {
     let mut image = DynamicImage::new_rgba8(w, h);
     for x in 0..w {
         for y in 0..h {
-            image.put_pixel(x, y, Rgba::from([ ((x % 2) * 255) as u8, ((y % 2) * 255) as u8, 0, 255]));
+            image.put_pixel(x, y, Rgba::from([0u8, 0, 0, 255]));
         }
     }
     image
@@ -0,0 +1,243 @@
+use crate::game::{BlockData, BlockFace, BlockId, DenseBools};
+use crate::mat::Vec3;
+
+/** Номер состояния блока. Состояние - это набор значений всех свойств блока,
+    номер считается по свойствам в порядке их объявления (младшее свойство - первое) */
+pub type BlockState = u16;
+
+/** Как значение свойства влияет на модель блока */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PropertyKind {
+    /** Ось модели: "x", "y", "z". Модель по умолчанию направлена вдоль Z */
+    Axis,
+    /** Сторона, в которую смотрит модель: "px", "nx", "py", "ny", "pz", "nz". По умолчанию - +X */
+    Facing,
+    /** Свойство не поворачивает модель, но может менять текстуры (возраст, вариант и т.д.) */
+    Other,
+}
+
+/** Свойство состояния блока и его возможные значения. Первое значение - значение по умолчанию */
+#[derive(Clone, Debug)]
+pub struct BlockProperty {
+    pub name: String,
+    pub kind: PropertyKind,
+    pub values: Vec<String>,
+}
+impl BlockProperty {
+    pub fn new<S: Into<String>>(name: S, values: Vec<&str>) -> Self {
+        let name: String = name.into();
+        let kind = match &name[..] {
+            "axis" => PropertyKind::Axis,
+            "facing" => PropertyKind::Facing,
+            _ => PropertyKind::Other,
+        };
+        Self { name, kind, values: values.iter().map(|v| v.to_string()).collect() }
+    }
+    pub fn axis() -> Self { Self::new("axis", vec!["z", "x", "y"]) }
+    pub fn facing() -> Self { Self::new("facing", vec!["px", "nx", "py", "ny", "pz", "nz"]) }
+    pub fn horizontal_facing() -> Self { Self::new("facing", vec!["px", "nx", "py", "ny"]) }
+
+    /** Поворот модели для значения свойства */
+    pub fn rotation(&self, value: &str) -> BlockRotation {
+        use BlockFace::*;
+        match (self.kind, value) {
+            (PropertyKind::Axis, "x")   => BlockRotation::from_axes(NZ, PY, PX),
+            (PropertyKind::Axis, "y")   => BlockRotation::from_axes(PX, NZ, PY),
+            (PropertyKind::Facing, "nx") => BlockRotation::from_axes(NX, NY, PZ),
+            (PropertyKind::Facing, "py") => BlockRotation::from_axes(PY, NX, PZ),
+            (PropertyKind::Facing, "ny") => BlockRotation::from_axes(NY, PX, PZ),
+            (PropertyKind::Facing, "pz") => BlockRotation::from_axes(PZ, PY, NX),
+            (PropertyKind::Facing, "nz") => BlockRotation::from_axes(NZ, PY, PX),
+            _ => BlockRotation::IDENTITY,
+        }
+    }
+}
+
+/** Замена текстур блока, когда свойство принимает определенное значение */
+#[derive(Clone, Debug)]
+pub struct TextureOverride {
+    pub property: String,
+    pub value: String,
+    pub textures: Vec<u32>,
+}
+
+/** Замена модели блока, когда свойство принимает определенное значение (например, верхняя и нижняя половина) */
+#[derive(Clone, Debug)]
+pub struct ModelOverride {
+    pub property: String,
+    pub value: String,
+    pub model_id: usize,
+}
+
+/** Поворот модели блока на прямые углы. Хранится как матрица, столбцы которой - образы осей X, Y, Z */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockRotation(pub [[i32; 3]; 3]);
+impl BlockRotation {
+    pub const IDENTITY: BlockRotation = BlockRotation([[1, 0, 0], [0, 1, 0], [0, 0, 1]]);
+
+    /** Поворот, переводящий оси X, Y и Z в направления указанных сторон */
+    pub fn from_axes(x: BlockFace, y: BlockFace, z: BlockFace) -> Self {
+        let to_arr = |f: BlockFace| { let o = f.offset(); [o.0, o.1, o.2] };
+        BlockRotation([to_arr(x), to_arr(y), to_arr(z)])
+    }
+
+    pub fn is_identity(&self) -> bool { *self == Self::IDENTITY }
+
+    pub fn apply(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        let (x, y, z) = (v.x(), v.y(), v.z());
+        Vec3::new(
+            x * m[0][0] as f32 + y * m[1][0] as f32 + z * m[2][0] as f32,
+            x * m[0][1] as f32 + y * m[1][1] as f32 + z * m[2][1] as f32,
+            x * m[0][2] as f32 + y * m[1][2] as f32 + z * m[2][2] as f32,
+        )
+    }
+
+    /** Сторона, в которую переходит сторона face при повороте */
+    pub fn apply_face(&self, face: BlockFace) -> BlockFace {
+        let o = face.offset();
+        let v = self.apply(Vec3::new(o.0 as f32, o.1 as f32, o.2 as f32));
+        let target = (v.x().round() as i32, v.y().round() as i32, v.z().round() as i32);
+        *BlockFace::ALL.iter().find(|f| f.offset() == target).unwrap()
+    }
+
+    /** Перестановка битов сторон (как в DenseBools) в соответствии с поворотом */
+    pub fn apply_faces_mask(&self, mask: u8) -> u8 {
+        let mut res = 0u8;
+        for face in BlockFace::ALL {
+            if mask & (1u8 << u8::from(face)) != 0 {
+                res |= 1u8 << u8::from(self.apply_face(face));
+            }
+        }
+        res
+    }
+
+    /** Краткая запись для имени повернутой модели */
+    pub fn suffix(&self) -> String {
+        let m = &self.0;
+        let mut res = String::new();
+        for axis in m.iter() {
+            for c in axis.iter() {
+                res.push(match c { 1 => 'p', -1 => 'n', _ => '0' });
+            }
+        }
+        res
+    }
+}
+
+/** Блок в конкретном состоянии вместе с его сплошными сторонами - все, что нужно, чтобы поставить его в чанк */
+#[derive(Clone, Copy, Debug)]
+pub struct BlockRef {
+    pub id: BlockId,
+    pub state: BlockState,
+    pub solid_faces: DenseBools,
+}
+
+/** Модель и текстуры, которыми отображается конкретное состояние блока */
+#[derive(Clone, Debug)]
+pub struct BlockVariant {
+    pub model_id: usize,
+    pub textures: Vec<u32>,
+}
+
+impl BlockData {
+    /** Количество различных состояний блока */
+    pub fn states_count(&self) -> usize {
+        self.properties.iter().map(|p| p.values.len().max(1)).product()
+    }
+
+    /** Значение свойства в данном состоянии */
+    pub fn property_value(&self, state: BlockState, property: &str) -> Option<&str> {
+        let mut rest = state as usize;
+        for p in self.properties.iter() {
+            let count = p.values.len().max(1);
+            if p.name == property {
+                return p.values.get(rest % count).map(|v| &v[..]);
+            }
+            rest /= count;
+        }
+        None
+    }
+
+    /** Номер состояния по значениям свойств. Неуказанные свойства принимают значения по умолчанию */
+    pub fn state(&self, values: &[(&str, &str)]) -> Option<BlockState> {
+        let mut state = 0usize;
+        let mut multiplier = 1usize;
+        for p in self.properties.iter() {
+            if let Some((_, value)) = values.iter().find(|(name, _)| *name == p.name) {
+                state += multiplier * p.values.iter().position(|v| v == value)?;
+            }
+            multiplier *= p.values.len().max(1);
+        }
+        Some(state as BlockState)
+    }
+
+    /** Модель (до поворота), поворот модели и текстуры для состояния (без учета уже созданных вариантов).
+        Поворот задает единственное свойство с поворотом (см. Game::add_block) */
+    pub fn resolve_state(&self, state: BlockState) -> (usize, BlockRotation, Vec<u32>) {
+        let mut model_id = self.model_id;
+        let mut rotation = BlockRotation::IDENTITY;
+        let mut textures = self.textures.clone();
+        for p in self.properties.iter() {
+            let value = self.property_value(state, &p.name).unwrap_or("");
+            if p.kind != PropertyKind::Other {
+                rotation = p.rotation(value);
+            }
+            for o in self.model_overrides.iter() {
+                if o.property == p.name && o.value == value {
+                    model_id = o.model_id;
+                }
+            }
+            for o in self.texture_overrides.iter() {
+                if o.property == p.name && o.value == value {
+                    textures = o.textures.clone();
+                }
+            }
+        }
+        (model_id, rotation, textures)
+    }
+
+    /** Модель и текстуры состояния. Несуществующие состояния отображаются как состояние по умолчанию */
+    pub fn variant(&self, state: BlockState) -> &BlockVariant {
+        self.variants.get(state as usize).unwrap_or(&self.variants[0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, RegistryError, RenderLayer};
+    use super::*;
+
+    fn block(name: &str, properties: Vec<BlockProperty>) -> BlockData {
+        BlockData {
+            model_id: 1,
+            textures: vec![1],
+            name: name.into(),
+            properties,
+            texture_overrides: vec![],
+            model_overrides: vec![],
+            variants: vec![],
+            emission: 0,
+            render_layer: RenderLayer::Opaque,
+        }
+    }
+
+    #[test]
+    fn only_one_property_rotates_model() {
+        let mut game = test_game();
+        let res = game.add_block(block("rotated_twice", vec![BlockProperty::axis(), BlockProperty::new("size", vec!["a", "b"]), BlockProperty::facing()]));
+        match res {
+            Err(RegistryError::MultipleRotations(block, first, second)) =>
+                assert_eq!((&block[..], &first[..], &second[..]), ("rotated_twice", "axis", "facing")),
+            other => panic!("{:?}", other),
+        }
+        assert!(game.get_block_id("rotated_twice".into()).is_err());
+
+        //Свойства без поворота не мешают
+        let id = game.add_block(block("rotated_once", vec![BlockProperty::new("size", vec!["a", "b"]), BlockProperty::horizontal_facing()])).unwrap();
+        let data = &game.blocks[id];
+        assert_eq!(data.variants.len(), 8);
+        let facing_ny = data.state(&[("size", "b"), ("facing", "ny")]).unwrap();
+        assert_eq!(data.resolve_state(facing_ny).1, BlockProperty::horizontal_facing().rotation("ny"));
+    }
+}
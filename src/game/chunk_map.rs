@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::collections::hash_map::{Values, ValuesMut};
+use crate::game::{Chunk, CHUNK_SIZE};
+
+/** Координаты чанка (в чанках, а не в блоках) */
+pub type ChunkPos = (i32, i32, i32);
+
+/** Разреженное хранилище чанков без ограничений на размер мира.
+    Поиск чанка по его координатам - O(1) */
+#[derive(Default)]
+pub struct ChunkMap {
+    chunks: HashMap<ChunkPos, Chunk>,
+}
+impl ChunkMap {
+    pub fn new() -> Self { Self { chunks: HashMap::new() } }
+
+    /** Загрузить чанк. Если на его месте уже был чанк - он будет заменен и возвращен */
+    pub fn insert(&mut self, chunk: Chunk) -> Option<Chunk> {
+        self.chunks.insert(chunk.pos(), chunk)
+    }
+    /** Выгрузить чанк */
+    pub fn remove(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunks.remove(&pos)
+    }
+
+    pub fn get(&self, pos: ChunkPos) -> Option<&Chunk> { self.chunks.get(&pos) }
+    pub fn get_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> { self.chunks.get_mut(&pos) }
+    pub fn contains(&self, pos: ChunkPos) -> bool { self.chunks.contains_key(&pos) }
+
+    pub fn len(&self) -> usize { self.chunks.len() }
+    pub fn is_empty(&self) -> bool { self.chunks.is_empty() }
+
+    pub fn positions(&self) -> impl Iterator<Item = ChunkPos> + '_ { self.chunks.keys().copied() }
+    pub fn iter(&self) -> Values<'_, ChunkPos, Chunk> { self.chunks.values() }
+    pub fn iter_mut(&mut self) -> ValuesMut<'_, ChunkPos, Chunk> { self.chunks.values_mut() }
+}
+
+/** Чанк, содержащий блок на мировых координатах x, y, z */
+pub fn chunk_pos_of(x: i32, y: i32, z: i32) -> ChunkPos {
+    let chs = CHUNK_SIZE as i32;
+    (x.div_euclid(chs), y.div_euclid(chs), z.div_euclid(chs))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::game::{test_game, Chunk, DenseBools};
+    use super::*;
+
+    #[test]
+    fn insert_get_remove() {
+        let mut map = ChunkMap::new();
+        assert!(map.is_empty());
+        assert!(map.insert(Chunk::empty(0, 0, 0)).is_none());
+        assert!(map.insert(Chunk::empty(-100000, 5000, 77)).is_none());
+        assert_eq!(map.len(), 2);
+        assert!(map.contains((-100000, 5000, 77)));
+        assert!(!map.contains((1, 0, 0)));
+        assert_eq!(map.get((-100000, 5000, 77)).map(|c| c.pos()), Some((-100000, 5000, 77)));
+        assert!(map.get((1, 0, 0)).is_none());
+
+        map.get_mut((0, 0, 0)).unwrap().set_block(3, 1, 2, 3, DenseBools(63));
+        assert_eq!(map.get((0, 0, 0)).unwrap().block(1, 2, 3), 3);
+
+        //Замена чанка на той же позиции возвращает старый
+        let old = map.insert(Chunk::empty(0, 0, 0)).unwrap();
+        assert_eq!(old.block(1, 2, 3), 3);
+        assert_eq!(map.get((0, 0, 0)).unwrap().block(1, 2, 3), 0);
+        assert_eq!(map.len(), 2);
+
+        let mut positions: Vec<ChunkPos> = map.positions().collect();
+        positions.sort();
+        assert_eq!(positions, vec![(-100000, 5000, 77), (0, 0, 0)]);
+
+        assert_eq!(map.remove((0, 0, 0)).map(|c| c.pos()), Some((0, 0, 0)));
+        assert!(map.remove((0, 0, 0)).is_none());
+        assert!(!map.contains((0, 0, 0)));
+        assert_eq!(map.len(), 1);
+    }
+
+    #[test]
+    fn chunk_pos_of_negative() {
+        assert_eq!(chunk_pos_of(0, 31, 32), (0, 0, 1));
+        assert_eq!(chunk_pos_of(-1, -32, -33), (-1, -1, -2));
+    }
+
+    #[test]
+    fn remove_chunk_drops_everything() {
+        let mut game = test_game();
+        let pos = (2, -1, 0);
+        game.add_chunk(Chunk::empty(pos.0, pos.1, pos.2));
+        assert!(game.is_chunk_loaded(pos));
+        assert!(game.dirty.contains(&pos));
+
+        //Модель пустого чанка строится без обращений к видеокарте
+        game.update_chunk_models(Duration::from_millis(100));
+        assert!(game.chunk_model(pos).is_some());
+        assert!(game.chunk_visibility(pos).is_some());
+        assert!(!game.dirty.contains(&pos));
+
+        game.set_block(3, 64, -20, 5);
+        assert!(game.dirty.contains(&pos));
+        game.meshing.insert(pos, 1);
+
+        let removed = game.remove_chunk(pos).unwrap();
+        assert_eq!(removed.block(0, 12, 5), 3);
+        assert!(!game.is_chunk_loaded(pos));
+        assert!(game.chunk_model(pos).is_none());
+        assert!(game.chunk_visibility(pos).is_none());
+        assert!(!game.dirty.contains(&pos));
+        assert!(!game.meshing.contains_key(&pos));
+        assert!(game.remove_chunk(pos).is_none());
+    }
+}
@@ -0,0 +1,248 @@
+use crate::game::raycast::cross;
+use crate::game::{BlockFace, BlockModel, BMShape, DenseBools};
+use crate::mat::Vec3;
+
+/** Точность сравнения координат вершин с границами блока */
+const EPS: f32 = 1e-4;
+/** Сколько точек вдоль каждой оси стороны проверяется на покрытие.
+    Модели собираются по сетке в 1/16 блока, так что щель между фигурами не пропускается */
+const COVERAGE_SAMPLES: usize = 32;
+
+/** Номер оси стороны (0 - x, 1 - y, 2 - z) и направление ее нормали */
+fn face_axis(face: BlockFace) -> (usize, f32) {
+    let (x, y, z) = face.offset();
+    match (x, y, z) {
+        (x, 0, 0) => (0, x as f32),
+        (0, y, 0) => (1, y as f32),
+        (_, _, z) => (2, z as f32),
+    }
+}
+fn coord(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+/** Лежит ли треугольник на стороне блока face и смотрит ли наружу */
+fn lies_on_face(triangle: &[Vec3; 3], face: BlockFace) -> bool {
+    let (axis, sign) = face_axis(face);
+    let normal = cross(triangle[1] - triangle[0], triangle[2] - triangle[0]);
+    triangle.iter().all(|p| (coord(*p, axis) * sign - 0.5).abs() < EPS) && coord(normal, axis) * sign > 0.0
+}
+
+/** Углы стороны блока */
+fn face_corners(face: BlockFace) -> Vec<Vec3> {
+    let (axis, sign) = face_axis(face);
+    let mut corners = vec![];
+    for x in [-0.5, 0.5] {
+        for y in [-0.5, 0.5] {
+            for z in [-0.5, 0.5] {
+                let corner = Vec3::new(x, y, z);
+                if coord(corner, axis) * sign > 0.0 { corners.push(corner); }
+            }
+        }
+    }
+    corners
+}
+
+/** Попадает ли точка (u, v) в проекцию треугольника на плоскость осей u, v (включая границы) */
+fn projection_contains(triangle: &[Vec3; 3], (u_axis, v_axis): (usize, usize), (u, v): (f32, f32)) -> bool {
+    let p: Vec<(f32, f32)> = triangle.iter().map(|p| (coord(*p, u_axis), coord(*p, v_axis))).collect();
+    let side = |a: (f32, f32), b: (f32, f32)| (b.0 - a.0) * (v - a.1) - (b.1 - a.1) * (u - a.0);
+    let (d0, d1, d2) = (side(p[0], p[1]), side(p[1], p[2]), side(p[2], p[0]));
+    (d0 >= -EPS && d1 >= -EPS && d2 >= -EPS) || (d0 <= EPS && d1 <= EPS && d2 <= EPS)
+}
+
+impl BMShape {
+    fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        self.indices.iter().map(|i| [self.vertices[i.0 as usize].pos, self.vertices[i.1 as usize].pos, self.vertices[i.2 as usize].pos])
+    }
+
+    /** Стороны блока, на которых лежит хотя бы один треугольник фигуры (лицом наружу) */
+    pub fn lying_faces(&self) -> u8 {
+        let mut result = 0u8;
+        for triangle in self.triangles() {
+            for face in BlockFace::ALL {
+                if lies_on_face(&triangle, face) { result |= 1 << usize::from(face); }
+            }
+        }
+        result
+    }
+
+    /** Стороны блока, через которые фигуру можно увидеть снаружи (то же, что overlap_state: фигура не видна,
+        когда все они закрыты соседями). Треугольник на стороне блока виден только через нее,
+        остальные - через стороны, хотя бы часть которых лежит перед плоскостью треугольника */
+    pub fn visible_through(&self) -> DenseBools {
+        let mut result = 0u8;
+        for triangle in self.triangles() {
+            let normal = cross(triangle[1] - triangle[0], triangle[2] - triangle[0]);
+            if normal.len() < EPS * EPS { continue; }
+            if let Some(face) = BlockFace::ALL.iter().find(|f| lies_on_face(&triangle, **f)) {
+                result |= 1 << usize::from(*face);
+                continue;
+            }
+            for face in BlockFace::ALL {
+                if face_corners(face).iter().any(|c| (*c - triangle[0]) * normal > EPS * normal.len()) {
+                    result |= 1 << usize::from(face);
+                }
+            }
+        }
+        DenseBools(result)
+    }
+}
+
+impl BlockModel {
+    /** Стороны блока, которые модель закрывает целиком (то же, что solid_faces): треугольники,
+        лежащие на стороне лицом наружу, покрывают весь квадрат стороны */
+    pub fn covered_faces(&self) -> DenseBools {
+        self.covered_faces_among(0b00111111)
+    }
+    /** То же, что covered_faces, но проверяются только стороны из маски faces */
+    pub fn covered_faces_among(&self, faces: u8) -> DenseBools {
+        let mut result = 0u8;
+        for face in BlockFace::ALL.into_iter().filter(|f| faces & (1 << usize::from(*f)) != 0) {
+            let triangles: Vec<[Vec3; 3]> = self.shapes.iter().flatten()
+                .flat_map(|s| s.triangles())
+                .filter(|t| lies_on_face(t, face))
+                .collect();
+            //Быстрая проверка: площади не хватает даже без наложений
+            let area: f32 = triangles.iter().map(|t| cross(t[1] - t[0], t[2] - t[0]).len() / 2.0).sum();
+            if area < 1.0 - EPS { continue; }
+
+            let (axis, _) = face_axis(face);
+            let axes = ((axis + 1) % 3, (axis + 2) % 3);
+            let step = 1.0 / COVERAGE_SAMPLES as f32;
+            let covered = (0..COVERAGE_SAMPLES * COVERAGE_SAMPLES).all(|i| {
+                let point = ((i % COVERAGE_SAMPLES) as f32 * step + step / 2.0 - 0.5, (i / COVERAGE_SAMPLES) as f32 * step + step / 2.0 - 0.5);
+                triangles.iter().any(|t| projection_contains(t, axes, point))
+            });
+            if covered { result |= 1 << usize::from(face); }
+        }
+        DenseBools(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{cross_block_model, cube_block_model, cylinder_block_model, fence_block_model, pane_block_model,
+                      slab_block_model, stairs_block_model, wall_block_model, ShapeVertex};
+    use BlockFace::*;
+    use super::*;
+
+    fn bit(face: BlockFace) -> u8 { 1 << usize::from(face) }
+
+    /** Сторона блока, в которую смотрит нормаль плоской фигуры */
+    fn normal_face(shape: &BMShape) -> BlockFace {
+        let normal = shape.vertices[0].normal;
+        BlockFace::ALL.into_iter().find(|f| {
+            let (x, y, z) = f.offset();
+            normal * Vec3::new(x as f32, y as f32, z as f32) > 0.5
+        }).unwrap()
+    }
+
+    /** Маска, которую раньше ставил box_face: сторона, лежащая на границе блока, зависит только от нее, остальные - ни от чего */
+    fn old_box_mask(shape: &BMShape) -> u8 {
+        let face = normal_face(shape);
+        let (axis, sign) = face_axis(face);
+        if shape.vertices.iter().all(|v| (coord(v.pos, axis) * sign - 0.5).abs() < EPS) { bit(face) } else { 0 }
+    }
+
+    /** Фигуры моделей из параллелепипедов: на границе блока маска та же, что раньше. Внутренние стороны раньше
+        рисовались всегда, теперь видны хотя бы через свою сторону блока и никогда - через противоположную */
+    fn check_box_model(model: &BlockModel) {
+        for shape in model.shapes.iter().flatten() {
+            let mask = shape.visible_through().0;
+            let old = old_box_mask(shape);
+            if old != 0 {
+                assert_eq!(mask, old, "{}", model.name);
+            } else {
+                let face = normal_face(shape);
+                assert_ne!(mask & bit(face), 0, "{}", model.name);
+                assert_eq!(mask & bit(face.opposite()), 0, "{}", model.name);
+            }
+        }
+    }
+
+    #[test]
+    fn cube() {
+        let model = cube_block_model();
+        //Раньше: cube.solid() и set_face у каждой стороны
+        assert_eq!(model.covered_faces().0, 0b00111111);
+        for (face, group) in BlockFace::ALL.into_iter().zip(model.shapes.iter()) {
+            assert_eq!(group[0].visible_through().0, bit(face));
+        }
+    }
+
+    #[test]
+    fn cylinder() {
+        let model = cylinder_block_model();
+        //Восьмиугольные торцы не закрывают углы блока, сплошных сторон нет, как и раньше
+        assert_eq!(model.covered_faces().0, 0);
+        //Старые маски боковых сторон: 0b00110110, 0b00110101, 0b00111001, 0b00111010.
+        //Сторона 0 идет от (0, -0.5) к (0.5, 0) и смотрит в сторону +X и -Y, то есть видна через PX и NY, а сторона 2 -
+        //через NX и PY. В старых масках они были перепутаны, поэтому здесь маски сторон 0 и 2 поменяны местами
+        let sides: Vec<u8> = model.shapes[0].iter().map(|s| s.visible_through().0).collect();
+        assert_eq!(sides, vec![0b00111001, 0b00110101, 0b00110110, 0b00111010]);
+        assert_eq!(sides[0], bit(PX) | bit(NY) | bit(PZ) | bit(NZ));
+        //Торцы: раньше set_face(PZ) и set_face(NZ)
+        assert_eq!(model.shapes[1][0].visible_through().0, bit(PZ));
+        assert_eq!(model.shapes[2][0].visible_through().0, bit(NZ));
+    }
+
+    #[test]
+    fn slabs_and_stairs() {
+        //Раньше: solid_face(PZ) для верхней плиты, solid_face(NZ) для нижней, solid_face(PX).solid_face(NZ) для ступенек
+        assert_eq!(slab_block_model(false).covered_faces().0, bit(NZ));
+        assert_eq!(slab_block_model(true).covered_faces().0, bit(PZ));
+        assert_eq!(stairs_block_model().covered_faces().0, bit(PX) | bit(NZ));
+        check_box_model(&slab_block_model(false));
+        check_box_model(&slab_block_model(true));
+        check_box_model(&stairs_block_model());
+    }
+
+    #[test]
+    fn solid_faces_match_full_check() {
+        //BlockModel::add проверяет только стороны, на которые легла новая фигура, результат должен совпадать с полной проверкой
+        for model in [cube_block_model(), cylinder_block_model(), slab_block_model(false), slab_block_model(true), stairs_block_model(),
+                      fence_block_model(), wall_block_model(), pane_block_model(), cross_block_model()] {
+            assert_eq!(model.solid_faces().0, model.covered_faces().0, "{}", model.name);
+        }
+        //Сторона из двух половин становится сплошной со второй половиной
+        let half = |z0: f32, z1: f32| {
+            let mut shape = BMShape::new();
+            shape.vertex(ShapeVertex::new(0.5, -0.5, z0, 1.0, 0.0, 0.0, 0.0, 0.0))
+                .vertex(ShapeVertex::new(0.5, 0.5, z0, 1.0, 0.0, 0.0, 1.0, 0.0))
+                .vertex(ShapeVertex::new(0.5, 0.5, z1, 1.0, 0.0, 0.0, 1.0, 0.5))
+                .vertex(ShapeVertex::new(0.5, -0.5, z1, 1.0, 0.0, 0.0, 0.0, 0.5))
+                .index((0, 1, 2)).index((2, 3, 0));
+            shape
+        };
+        let mut model = BlockModel::new("halves".into());
+        model.add(half(-0.5, 0.0));
+        assert_eq!(model.solid_faces().0, 0);
+        model.add(half(0.0, 0.5));
+        assert_eq!(model.solid_faces().0, bit(PX));
+    }
+
+    #[test]
+    fn posts() {
+        for model in [fence_block_model(), wall_block_model(), pane_block_model()] {
+            assert_eq!(model.covered_faces().0, 0, "{}", model.name);
+            check_box_model(&model);
+        }
+    }
+
+    #[test]
+    fn cross() {
+        let model = cross_block_model();
+        assert_eq!(model.covered_faces().0, 0);
+        //Диагональные плоскости видны сверху, снизу и с двух сторон, в которые смотрят
+        for shape in model.shapes.iter().flatten() {
+            let mask = shape.visible_through().0;
+            assert_eq!(mask & (bit(PZ) | bit(NZ)), bit(PZ) | bit(NZ));
+            assert_eq!(mask.count_ones(), 4);
+        }
+    }
+}
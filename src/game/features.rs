@@ -0,0 +1,222 @@
+use crate::game::{BlockRef, Chunk, ChunkPos, SplitMix64, hash_coords, CHUNK_SIZE};
+
+/** Блоки, которые структуры ставят в один конкретный чанк.
+    Позиции за пределами чанка отбрасываются: соседний чанк при генерации заново построит те же
+    структуры и заберет свою часть, поэтому структуры не обрезаются на границах чанков */
+pub struct DecorationBuffer {
+    chunk: ChunkPos,
+    blocks: Vec<(i32, i32, i32, BlockRef, bool)>,   //Локальная позиция, блок, ставить только в воздух
+}
+impl DecorationBuffer {
+    pub fn new(chunk: ChunkPos) -> Self { Self { chunk, blocks: vec![] } }
+
+    /** Поставить блок по мировым координатам, заменяя то, что там было */
+    pub fn set(&mut self, x: i32, y: i32, z: i32, block: BlockRef) {
+        self.push(x, y, z, block, false);
+    }
+    /** Поставить блок по мировым координатам, только если там воздух */
+    pub fn set_if_air(&mut self, x: i32, y: i32, z: i32, block: BlockRef) {
+        self.push(x, y, z, block, true);
+    }
+    fn push(&mut self, x: i32, y: i32, z: i32, block: BlockRef, only_air: bool) {
+        let chs = CHUNK_SIZE as i32;
+        let local = (x - self.chunk.0 * chs, y - self.chunk.1 * chs, z - self.chunk.2 * chs);
+        if Chunk::is_inside(local.0, local.1, local.2) {
+            self.blocks.push((local.0, local.1, local.2, block, only_air));
+        }
+    }
+
+    pub fn len(&self) -> usize { self.blocks.len() }
+    pub fn is_empty(&self) -> bool { self.blocks.is_empty() }
+
+    /** Записать блоки в чанк в порядке их добавления */
+    pub fn apply(&self, chunk: &mut Chunk) {
+        for (x, y, z, block, only_air) in self.blocks.iter() {
+            if *only_air && chunk.block(*x, *y, *z) != 0 { continue; }
+            chunk.put_block(*block, *x, *y, *z);
+        }
+    }
+}
+
+/** Структура, которую можно поставить на поверхность (дерево, валун и т.д.) */
+pub trait Feature: Send + Sync {
+    /** На сколько блоков по горизонтали структура может отходить от своего основания */
+    fn reach(&self) -> i32;
+    /** Построить структуру с основанием над блоком поверхности (x, y, z) */
+    fn place(&self, x: i32, y: i32, z: i32, rng: &mut SplitMix64, out: &mut DecorationBuffer);
+}
+
+/** Где ставятся структуры */
+#[derive(Clone, Copy, Debug)]
+pub enum Placement {
+    /** В каждом столбце с вероятностью chance */
+    Random { chance: f64 },
+    /** В столбцах с координатами offset + k * step по обеим осям */
+    Grid { step: i32, offset: (i32, i32) },
+}
+impl Placement {
+    fn is_origin(&self, x: i32, y: i32, rng: &mut SplitMix64) -> bool {
+        match *self {
+            Placement::Random { chance } => rng.next_f64() < chance,
+            Placement::Grid { step, offset } => (x - offset.0).rem_euclid(step) == 0 && (y - offset.1).rem_euclid(step) == 0,
+        }
+    }
+}
+
+/** Набор структур генератора и правила их расстановки */
+pub struct Decorator {
+    features: Vec<(Box<dyn Feature>, Placement)>,
+}
+impl Decorator {
+    pub fn new() -> Self { Self { features: vec![] } }
+
+    pub fn add<F: Feature + 'static>(&mut self, feature: F, placement: Placement) -> &mut Self {
+        self.features.push((Box::new(feature), placement));
+        self
+    }
+
+    /** Достроить в чанке все структуры, которые его задевают, включая структуры из соседних столбцов.
+        surface_height - высота поверхности в мировом столбце (x, y) */
+    pub fn decorate<H>(&self, seed: u64, chunk: &mut Chunk, surface_height: H)
+        where H: Fn(i32, i32) -> i32
+    {
+        let chs = CHUNK_SIZE as i32;
+        let pos = chunk.pos();
+        let mut buffer = DecorationBuffer::new(pos);
+
+        for (feature_id, (feature, placement)) in self.features.iter().enumerate() {
+            let reach = feature.reach();
+            let feature_seed = seed ^ (feature_id as u64 + 1).wrapping_mul(0xA24BAED4963EE407);
+            for x in (pos.0 * chs - reach)..((pos.0 + 1) * chs + reach) {
+                for y in (pos.1 * chs - reach)..((pos.1 + 1) * chs + reach) {
+                    //Решение о структуре зависит только от сида и столбца, но не от того, какой чанк генерируется
+                    let mut rng = SplitMix64(hash_coords(feature_seed, x, y, 0));
+                    if !placement.is_origin(x, y, &mut rng) { continue; }
+                    feature.place(x, y, surface_height(x, y), &mut rng, &mut buffer);
+                }
+            }
+        }
+        buffer.apply(chunk);
+    }
+}
+impl Default for Decorator {
+    fn default() -> Self { Self::new() }
+}
+
+/** Дерево: ствол и шарообразная крона */
+pub struct TreeFeature {
+    pub log: BlockRef,
+    pub leaves: BlockRef,
+    /** Высота ствола (от и до включительно) */
+    pub trunk_height: (i32, i32),
+    pub crown_radius: i32,
+    /** Лист ставится, если квадрат расстояния до центра кроны не больше этого значения */
+    pub crown_dist_sq: i32,
+    /** Центр кроны на столько блоков выше верхушки ствола */
+    pub crown_offset: i32,
+}
+impl Feature for TreeFeature {
+    fn reach(&self) -> i32 { self.crown_radius }
+    fn place(&self, x: i32, y: i32, z: i32, rng: &mut SplitMix64, out: &mut DecorationBuffer) {
+        let (min, max) = self.trunk_height;
+        let height = min + (rng.next_u64() % (max - min + 1) as u64) as i32;
+        let crown_z = z + height + self.crown_offset;
+        let r = self.crown_radius;
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    if dx*dx + dy*dy + dz*dz <= self.crown_dist_sq {
+                        out.set_if_air(x + dx, y + dy, crown_z + dz, self.leaves);
+                    }
+                }
+            }
+        }
+        for dz in 1..=height {
+            out.set(x, y, z + dz, self.log);
+        }
+    }
+}
+
+/** Валун: неровный шар, наполовину утопленный в землю */
+pub struct BoulderFeature {
+    pub block: BlockRef,
+    /** Радиус (от и до включительно) */
+    pub radius: (i32, i32),
+}
+impl Feature for BoulderFeature {
+    fn reach(&self) -> i32 { self.radius.1 }
+    fn place(&self, x: i32, y: i32, z: i32, rng: &mut SplitMix64, out: &mut DecorationBuffer) {
+        let (min, max) = self.radius;
+        let r = min + (rng.next_u64() % (max - min + 1) as u64) as i32;
+        //Небольшое растяжение по осям, чтобы валуны не были одинаковыми шарами
+        let (sx, sy, sz) = (0.8 + rng.next_f64() * 0.4, 0.8 + rng.next_f64() * 0.4, 0.7 + rng.next_f64() * 0.3);
+        let rf = r as f64 + 0.5;
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -r..=r {
+                    let d = (dx as f64 / sx).powi(2) + (dy as f64 / sy).powi(2) + (dz as f64 / sz).powi(2);
+                    if d <= rf * rf {
+                        out.set(x + dx, y + dy, z + dz, self.block);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::game::{test_game, BlockId};
+    use super::*;
+
+    #[test]
+    fn tree_on_chunk_border_is_complete() {
+        let game = test_game();
+        let (log, leaves) = (game.block_ref("log").unwrap(), game.block_ref("leaves").unwrap());
+        //Ствол в последнем столбце чанка (0, 0, 0), крона заходит в чанк (1, 0, 0). Высота ствола случайная
+        let mut decorator = Decorator::new();
+        decorator.add(TreeFeature {
+            log,
+            leaves,
+            trunk_height: (4, 6),
+            crown_radius: 2,
+            crown_dist_sq: 5,
+            crown_offset: 0,
+        }, Placement::Grid { step: 1000, offset: (31, 10) });
+        let generate = |pos: ChunkPos| {
+            let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+            decorator.decorate(7, &mut chunk, |_, _| 3);
+            chunk
+        };
+
+        let mut worlds = vec![];
+        for order in [[(0, 0, 0), (1, 0, 0)], [(1, 0, 0), (0, 0, 0)]] {
+            let mut game = test_game();
+            for pos in order { game.add_chunk(generate(pos)); }
+            let blocks: HashSet<(i32, i32, i32, BlockId)> = (26..38)
+                .flat_map(|x| (5..16).flat_map(move |y| (0..16).map(move |z| (x, y, z))))
+                .filter_map(|(x, y, z)| game.block_at(x, y, z).filter(|b| b.0 != 0).map(|b| (x, y, z, b.0)))
+                .collect();
+            worlds.push(blocks);
+        }
+        assert!(worlds[0] == worlds[1]);
+
+        //Ствол с основанием над поверхностью z = 3 и целая крона вокруг его верхушки, по обе стороны границы
+        let blocks = &worlds[0];
+        let height = (4..=6).find(|h| blocks.contains(&(31, 10, 3 + h, log.id)) && !blocks.contains(&(31, 10, 4 + h, log.id))).unwrap();
+        let mut expected: HashSet<(i32, i32, i32, BlockId)> = (1..=height).map(|dz| (31, 10, 3 + dz, log.id)).collect();
+        for dx in -2..=2 {
+            for dy in -2..=2 {
+                for dz in -2..=2 {
+                    let pos = (31 + dx, 10 + dy, 3 + height + dz);
+                    if dx*dx + dy*dy + dz*dz <= 5 && !expected.contains(&(pos.0, pos.1, pos.2, log.id)) {
+                        expected.insert((pos.0, pos.1, pos.2, leaves.id));
+                    }
+                }
+            }
+        }
+        assert!(expected.iter().any(|b| b.0 >= CHUNK_SIZE as i32));
+        assert!(*blocks == expected);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::game::{BlockRef, BoulderFeature, Chunk, ChunkPos, Decorator, Game, Noise, Placement, RegistryError, TreeFeature, hash_coords, CHUNK_SIZE};
+
+/** Генератор мира: по сиду и координатам чанка возвращает заполненный чанк.
+    Одинаковые сид и координаты всегда должны давать одинаковый чанк */
+pub trait WorldGenerator: Send + Sync {
+    fn generate(&self, seed: u64, pos: ChunkPos) -> Chunk;
+    /** Высота верхнего блока поверхности в столбце (мировые координаты), на нее ставятся структуры */
+    fn surface_height(&self, seed: u64, x: i32, y: i32) -> i32;
+}
+
+/** Встроенные генераторы */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GeneratorPreset {
+    /** Волны вокруг начала координат с деревьями (первый генератор, не зависит от сида) */
+    Classic,
+    /** Холмы из фрактального шума, пещеры и руды */
+    Noise,
+}
+impl GeneratorPreset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(GeneratorPreset::Classic),
+            "noise" => Some(GeneratorPreset::Noise),
+            _ => None,
+        }
+    }
+    pub fn create(&self, game: &Game) -> Result<Box<dyn WorldGenerator>, RegistryError> {
+        Ok(match self {
+            GeneratorPreset::Classic => Box::new(ClassicGenerator::new(game)?),
+            GeneratorPreset::Noise => Box::new(NoiseGenerator::new(game)?),
+        })
+    }
+}
+
+/** Поставить блок по мировым координатам, если они попадают в чанк */
+fn set_world_block(chunk: &mut Chunk, block: BlockRef, x: i32, y: i32, z: i32) {
+    let chs = CHUNK_SIZE as i32;
+    let (lx, ly, lz) = (x - chunk.x * chs, y - chunk.y * chs, z - chunk.z * chs);
+    if Chunk::is_inside(lx, ly, lz) {
+        chunk.put_block(block, lx, ly, lz);
+    }
+}
+
+pub struct ClassicGenerator {
+    stone: BlockRef,
+    grass: BlockRef,
+    lying_log: BlockRef,
+    decorator: Decorator,
+}
+impl ClassicGenerator {
+    pub fn new(game: &Game) -> Result<Self, RegistryError> {
+        let mut decorator = Decorator::new();
+        //Дерево в одном и том же месте каждого чанка
+        decorator.add(TreeFeature {
+            log: game.block_ref("log_cyl")?,
+            leaves: game.block_ref("leaves")?,
+            trunk_height: (7, 7),
+            crown_radius: 2,
+            crown_dist_sq: 2,
+            crown_offset: 1,
+        }, Placement::Grid { step: CHUNK_SIZE as i32, offset: (10, 10) });
+
+        Ok(Self {
+            stone: game.block_ref("stone")?,
+            grass: game.block_ref("grass")?,
+            lying_log: game.block_ref_with("log_cyl", &[("axis", "x")])?,
+            decorator,
+        })
+    }
+}
+impl WorldGenerator for ClassicGenerator {
+    fn generate(&self, seed: u64, pos: ChunkPos) -> Chunk {
+        let chs = CHUNK_SIZE as i32;
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        for x in 0..chs {
+            for y in 0..chs {
+                let (wx, wy) = (x + pos.0 * chs, y + pos.1 * chs);
+                let h = self.surface_height(seed, wx, wy);
+                for z in 0..h {
+                    set_world_block(&mut chunk, self.stone, wx, wy, z);
+                }
+                set_world_block(&mut chunk, self.grass, wx, wy, h);
+            }
+        }
+        self.decorator.decorate(seed, &mut chunk, |x, y| self.surface_height(seed, x, y));
+        set_world_block(&mut chunk, self.lying_log, 20, 20, 30);
+        chunk
+    }
+
+    fn surface_height(&self, _seed: u64, x: i32, y: i32) -> i32 {
+        let h = (x.pow(2) + y.pow(2)) as f32;
+        let h = (h * 0.25).sqrt().sin() + 1.0;
+        (h * 2.5).floor() as i32
+    }
+}
+
+/** Параметры генератора на шуме */
+#[derive(Clone, Debug)]
+pub struct NoiseSettings {
+    /** Средняя высота поверхности */
+    pub base_height: f64,
+    /** Наибольшее отклонение поверхности от средней высоты */
+    pub height_amplitude: f64,
+    /** Масштаб холмов в блоках */
+    pub terrain_scale: f64,
+    /** Толщина слоя земли под травой */
+    pub dirt_depth: i32,
+    /** Толщина пещер: чем больше, тем шире тоннели */
+    pub cave_width: f64,
+    /** Пещеры не поднимаются ближе, чем на столько блоков к поверхности */
+    pub cave_min_depth: i32,
+    /** Порог шума для угля и железа (чем выше, тем реже руда) */
+    pub coal_threshold: f64,
+    pub iron_threshold: f64,
+    /** Железо встречается не выше этой глубины под поверхностью */
+    pub iron_min_depth: i32,
+}
+impl Default for NoiseSettings {
+    fn default() -> Self {
+        Self {
+            base_height: 14.0,
+            height_amplitude: 12.0,
+            terrain_scale: 96.0,
+            dirt_depth: 3,
+            cave_width: 0.08,
+            cave_min_depth: 4,
+            coal_threshold: 0.5,
+            iron_threshold: 0.55,
+            iron_min_depth: 8,
+        }
+    }
+}
+
+//Каждый вид шума получает свой сид, чтобы они не повторяли друг друга
+const CAVES_SALT_A: u64 = 0x63617665_00000001;
+const CAVES_SALT_B: u64 = 0x63617665_00000002;
+const COAL_SALT:    u64 = 0x636f616c_00000000;
+const IRON_SALT:    u64 = 0x69726f6e_00000000;
+
+pub struct NoiseGenerator {
+    pub settings: NoiseSettings,
+
+    stone: BlockRef,
+    dirt: BlockRef,
+    grass: BlockRef,
+    coal_ore: BlockRef,
+    iron_ore: BlockRef,
+
+    pub decorator: Decorator,
+}
+impl NoiseGenerator {
+    pub fn new(game: &Game) -> Result<Self, RegistryError> {
+        let mut decorator = Decorator::new();
+        decorator.add(TreeFeature {
+            log: game.block_ref("log")?,
+            leaves: game.block_ref("leaves")?,
+            trunk_height: (4, 6),
+            crown_radius: 2,
+            crown_dist_sq: 5,
+            crown_offset: 0,
+        }, Placement::Random { chance: 0.008 })
+        .add(BoulderFeature {
+            block: game.block_ref("stone")?,
+            radius: (1, 2),
+        }, Placement::Random { chance: 0.0015 });
+
+        Ok(Self {
+            settings: NoiseSettings::default(),
+            stone: game.block_ref("stone")?,
+            dirt: game.block_ref("dirt")?,
+            grass: game.block_ref("grass")?,
+            coal_ore: game.block_ref("coal_ore")?,
+            iron_ore: game.block_ref("iron_ore")?,
+            decorator,
+        })
+    }
+
+    /** Высота поверхности в столбце (мировые координаты) */
+    fn height_at(&self, height_noise: &Noise, x: i32, y: i32) -> i32 {
+        let s = &self.settings;
+        let n = height_noise.fbm2(x as f64 / s.terrain_scale, y as f64 / s.terrain_scale, 5);
+        (s.base_height + n * s.height_amplitude).round() as i32
+    }
+}
+impl WorldGenerator for NoiseGenerator {
+    fn generate(&self, seed: u64, pos: ChunkPos) -> Chunk {
+        let s = &self.settings;
+        let chs = CHUNK_SIZE as i32;
+        let height_noise = Noise::new(seed);
+        //Пещеры - пересечение двух "оболочек" |шум| < ширины, получаются извилистые тоннели
+        let caves_a = Noise::new(seed ^ CAVES_SALT_A);
+        let caves_b = Noise::new(seed ^ CAVES_SALT_B);
+        let coal = Noise::new(seed ^ COAL_SALT);
+        let iron = Noise::new(seed ^ IRON_SALT);
+
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        for x in 0..chs {
+            for y in 0..chs {
+                let (wx, wy) = (x + pos.0 * chs, y + pos.1 * chs);
+                let h = self.height_at(&height_noise, wx, wy);
+
+                for z in 0..chs {
+                    let wz = z + pos.2 * chs;
+                    let depth = h - wz;
+                    if depth < 0 { break; }
+
+                    if depth >= s.cave_min_depth {
+                        let (fx, fy, fz) = (wx as f64 / 32.0, wy as f64 / 32.0, wz as f64 / 20.0);
+                        let a = caves_a.fbm3(fx, fy, fz, 2);
+                        let b = caves_b.fbm3(fx, fy, fz, 2);
+                        if a.abs() < s.cave_width && b.abs() < s.cave_width { continue; }
+                    }
+
+                    let block = if depth == 0 {
+                        self.grass
+                    } else if depth <= s.dirt_depth {
+                        self.dirt
+                    } else {
+                        let (fx, fy, fz) = (wx as f64 / 4.0, wy as f64 / 4.0, wz as f64 / 4.0);
+                        //Небольшой случайный сдвиг, чтобы края залежей не были слишком гладкими
+                        let jitter = (hash_coords(seed, wx, wy, wz) % 1000) as f64 / 1000.0 * 0.1;
+                        if depth >= s.iron_min_depth && iron.get3(fx, fy, fz) + jitter > s.iron_threshold {
+                            self.iron_ore
+                        } else if coal.get3(fx, fy, fz) + jitter > s.coal_threshold {
+                            self.coal_ore
+                        } else {
+                            self.stone
+                        }
+                    };
+                    chunk.put_block(block, x, y, z);
+                }
+            }
+        }
+        self.decorator.decorate(seed, &mut chunk, |x, y| self.height_at(&height_noise, x, y));
+        chunk
+    }
+
+    fn surface_height(&self, seed: u64, x: i32, y: i32) -> i32 {
+        self.height_at(&Noise::new(seed), x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, BlockId, BlockState};
+    use super::*;
+
+    /** Блоки и состояния всех клеток чанка */
+    fn cells(chunk: &Chunk) -> Vec<(BlockId, BlockState)> {
+        let chs = CHUNK_SIZE as i32;
+        (0..chs).flat_map(|z| (0..chs).flat_map(move |y| (0..chs).map(move |x| (x, y, z))))
+            .map(|(x, y, z)| (chunk.block(x, y, z), chunk.state(x, y, z)))
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_chunk() {
+        let game = test_game();
+        for preset in [GeneratorPreset::Classic, GeneratorPreset::Noise] {
+            //Два независимых генератора, как после перезапуска игры
+            let (a, b) = (preset.create(&game).unwrap(), preset.create(&game).unwrap());
+            for pos in [(0, 0, 0), (3, -2, 0), (-1, 5, -1)] {
+                let chunk = a.generate(42, pos);
+                assert_eq!(chunk.pos(), pos);
+                assert!(cells(&chunk) == cells(&b.generate(42, pos)), "{:?} {:?}", preset, pos);
+            }
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_terrain() {
+        //Классический генератор от сида не зависит, шумовой - зависит
+        let game = test_game();
+        let generator = GeneratorPreset::Noise.create(&game).unwrap();
+        let chunks = [1, 2].map(|seed| generator.generate(seed, (0, 0, 0)));
+        assert!(chunks.iter().all(|chunk| chunk.blocks_count() > 0));
+        assert!(cells(&chunks[0]) != cells(&chunks[1]));
+        assert!((0..CHUNK_SIZE as i32).any(|x| generator.surface_height(1, x * 5, 17) != generator.surface_height(2, x * 5, 17)));
+    }
+}
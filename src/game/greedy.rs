@@ -0,0 +1,236 @@
+use crate::game::{BMShape, BlockData, BlockModel, Chunk, ChunkHalo, ChunkMesh, RenderLayer, TexData, VertexLight, CHUNK_SIZE};
+use crate::mat::Vec3;
+
+/** Видимая сторона куба в срезе чанка. Стороны с равными ключами объединяются в один четырехугольник */
+#[derive(Clone, Copy, PartialEq)]
+struct FaceKey {
+    material: u32,
+    /** Случайное число блока. У материалов с одной текстурой не важно и равно нулю */
+    random: i32,
+    /** Освещение стороны, если оно одинаково во всех вершинах. Иначе сторона не объединяется с соседями */
+    light: Option<VertexLight>,
+}
+
+impl Chunk {
+    /** Добавить видимые стороны всех непрозрачных блоков с моделью cube жадным алгоритмом: в каждом срезе чанка
+        соседние стороны с одной текстурой и равномерным одинаковым освещением объединяются в прямоугольники.
+        Текстура на объединенной стороне повторяется (координаты текстуры выходят за [0; 1]) */
+    pub(super) fn add_greedy_cubes(&self, blocks_data: &[BlockData], cube: &BlockModel, cube_id: usize,
+                                   materials: &[TexData], halo: &ChunkHalo, mesh: &mut ChunkMesh) {
+        let chs = CHUNK_SIZE as i32;
+        for (face, group) in cube.shapes.iter().enumerate() {
+            let shape = &group[0];
+            //Ось нормали стороны и две оси среза
+            let axis = face / 2;
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let normal = shape.vertices[0].normal;
+            let (grad_u, grad_v) = (tex_gradient(shape, u, v), tex_gradient(shape, v, u));
+
+            for layer in 0..chs {
+                //Ключи видимых сторон среза, индекс - j * CHUNK_SIZE + i
+                let mut mask: Vec<Option<FaceKey>> = vec![None; CHUNK_SIZE * CHUNK_SIZE];
+                for j in 0..chs {
+                    for i in 0..chs {
+                        let mut p = [0; 3];
+                        p[axis] = layer;
+                        p[u] = i;
+                        p[v] = j;
+                        let id = Chunk::pos_id(p[0] as usize, p[1] as usize, p[2] as usize);
+                        let block = self.blocks.get(id) as usize;
+                        if block == 0 { continue; }
+                        let variant = blocks_data[block].variant(self.states.get(id));
+                        //Сторона закрыта соседом
+                        if variant.model_id != cube_id || blocks_data[block].render_layer != RenderLayer::Opaque || self.faces.get(id) & shape.overlap_state.0 == shape.overlap_state.0 {
+                            continue;
+                        }
+
+                        let material = variant.textures[face];
+                        let single_texture = materials.get(material as usize).is_some_and(|m| m.textures_count <= 1 && m.normals_count <= 1);
+                        let lights: Vec<VertexLight> = shape.vertices.iter()
+                            .map(|sv| halo.vertex_light(p[0], p[1], p[2], sv.pos, sv.normal))
+                            .collect();
+                        let uniform = lights.iter().all(|l| *l == lights[0]);
+                        mask[(j * chs + i) as usize] = Some(FaceKey {
+                            material,
+                            random: if single_texture { 0 } else { self.block_random(p[0], p[1], p[2]) },
+                            light: if uniform { Some(lights[0]) } else { None },
+                        });
+                    }
+                }
+
+                //Обход среза: прямоугольник растет сначала по i, затем по j
+                for j in 0..chs {
+                    for i in 0..chs {
+                        let key = match mask[(j * chs + i) as usize] {
+                            Some(key) => key,
+                            None => continue,
+                        };
+                        let same = |i: i32, j: i32| key.light.is_some() && mask[(j * chs + i) as usize] == Some(key);
+                        let mut w = 1;
+                        while i + w < chs && same(i + w, j) { w += 1; }
+                        let mut h = 1;
+                        while j + h < chs && (i..i + w).all(|i| same(i, j + h)) { h += 1; }
+                        for jj in j..j + h {
+                            for ii in i..i + w {
+                                mask[(jj * chs + ii) as usize] = None;
+                            }
+                        }
+
+                        let mut origin = [0; 3];
+                        origin[axis] = layer;
+                        origin[u] = i;
+                        origin[v] = j;
+                        let random = self.block_random(origin[0], origin[1], origin[2]);
+                        let quad = GreedyQuad { origin, size: (w, h), axes: (u, v), tex_gradient: (grad_u, grad_v) };
+                        quad.add(shape, key.material, random, normal, halo, mesh);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/** Прямоугольник из w * h сторон кубов одного среза */
+struct GreedyQuad {
+    /** Блок в углу с наименьшими координатами */
+    origin: [i32; 3],
+    size: (i32, i32),
+    /** Оси среза, вдоль которых идут размеры */
+    axes: (usize, usize),
+    /** Изменение координат текстуры на один блок вдоль осей среза */
+    tex_gradient: ((f32, f32), (f32, f32)),
+}
+impl GreedyQuad {
+    /** Добавить прямоугольник, растянув фигуру стороны куба shape: каждая вершина переносится в свой угол */
+    fn add(&self, shape: &BMShape, material: u32, random: i32, normal: Vec3, halo: &ChunkHalo, mesh: &mut ChunkMesh) {
+        let (u, v) = self.axes;
+        let start_index = mesh.vertices.len() as u32;
+        let mut shade = vec![];
+        for sv in shape.vertices.iter() {
+            let pos = [sv.pos.x(), sv.pos.y(), sv.pos.z()];
+            //Сдвиг в блоках до углового блока, к которому относится вершина
+            let du = if pos[u] > 0.0 { self.size.0 - 1 } else { 0 };
+            let dv = if pos[v] > 0.0 { self.size.1 - 1 } else { 0 };
+            let mut block = self.origin;
+            block[u] += du;
+            block[v] += dv;
+
+            let light = halo.vertex_light(block[0], block[1], block[2], sv.pos, normal);
+            shade.push(light[2]);
+            let mut res = *sv;
+            res.tex_x += self.tex_gradient.0.0 * du as f32 + self.tex_gradient.1.0 * dv as f32;
+            res.tex_y += self.tex_gradient.0.1 * du as f32 + self.tex_gradient.1.1 * dv as f32;
+            let offset = Vec3::new(block[0] as f32, block[1] as f32, block[2] as f32);
+            mesh.vertices.push(res.to_vertex(offset, material as i32, random, light));
+        }
+        let flipped = shape.flipped_quad(&shade);
+        for i in flipped.as_ref().unwrap_or(&shape.indices).iter() {
+            mesh.indices.push(i.0 + start_index);
+            mesh.indices.push(i.1 + start_index);
+            mesh.indices.push(i.2 + start_index);
+        }
+    }
+}
+
+/** Изменение координат текстуры на единицу вдоль оси axis. Берутся две вершины фигуры,
+    различающиеся только по этой оси (other - вторая ось плоскости фигуры) */
+fn tex_gradient(shape: &BMShape, axis: usize, other: usize) -> (f32, f32) {
+    let coords = |p: Vec3| [p.x(), p.y(), p.z()];
+    for a in shape.vertices.iter() {
+        for b in shape.vertices.iter() {
+            let (pa, pb) = (coords(a.pos), coords(b.pos));
+            if pa[other] == pb[other] && pa[axis] != pb[axis] {
+                let d = pb[axis] - pa[axis];
+                return ((b.tex_x - a.tex_x) / d, (b.tex_y - a.tex_y) / d);
+            }
+        }
+    }
+    (0.0, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, Vertex};
+    use super::*;
+
+    #[test]
+    fn flat_layer_is_merged() {
+        //Слой камня 32x32 в чанке без соседей
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                game.set_block(3, x, y, 0);
+            }
+        }
+        let chunk = game.chunk((0, 0, 0)).unwrap();
+        let halo = game.chunk_halo((0, 0, 0));
+
+        //Поблочно, как до жадного алгоритма: верх и низ каждого блока и стороны по краям слоя
+        let (mut vertices, mut indices): (Vec<Vertex>, Vec<u32>) = (vec![], vec![]);
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let id = Chunk::pos_id(x, y, 0);
+                let variant = game.blocks[3].variant(chunk.states.get(id));
+                let (bx, by) = (x as i32, y as i32);
+                game.models[variant.model_id].add_to_model_lit(
+                    Vec3::new(x as f32, y as f32, 0.0), (chunk.faces.get(id), 0), chunk.block_random(bx, by, 0),
+                    (&mut vertices, &mut indices), &variant.textures,
+                    |pos, normal| halo.vertex_light(bx, by, 0, pos, normal),
+                );
+            }
+        }
+        let faces = 2 * CHUNK_SIZE * CHUNK_SIZE + 4 * CHUNK_SIZE;
+        assert_eq!(vertices.len(), faces * 4);
+        assert_eq!(vertices.len(), 8704);
+        assert_eq!(indices.len(), faces * 6);
+
+        //Жадно: по одному четырехугольнику на сторону слоя
+        let meshes = chunk.build_mesh(&game.blocks, &game.models, game.atlas.textures(), &halo);
+        assert_eq!(meshes.opaque.vertices.len(), 24);
+        assert_eq!(meshes.opaque.indices.len(), 36);
+        assert!(meshes.cutout.is_empty() && meshes.translucent.is_empty());
+
+        //Объединенные четырехугольники покрывают ту же площадь с тем же материалом и светом
+        let (merged, per_block) = (sides(&meshes.opaque.vertices, &meshes.opaque.indices), sides(&vertices, &indices));
+        for (side, (merged, per_block)) in merged.iter().zip(per_block.iter()).enumerate() {
+            assert!((merged.0 - per_block.0).abs() < 1e-3, "площадь стороны {}: {} и {}", side, merged.0, per_block.0);
+            assert_eq!(merged.1, per_block.1, "материал и свет стороны {}", side);
+        }
+        assert_eq!(merged[4].0, (CHUNK_SIZE * CHUNK_SIZE) as f32);
+
+        //Текстура верхней стороны повторяется на всю ширину и длину слоя, а не растягивается
+        let top: Vec<_> = meshes.opaque.vertices.iter().filter(|v| v.shape_vert.normal.z() > 0.5).collect();
+        let span = |coord: fn(&&Vertex) -> f32| {
+            top.iter().map(coord).fold(f32::MIN, f32::max) - top.iter().map(coord).fold(f32::MAX, f32::min)
+        };
+        assert_eq!(span(|v| v.shape_vert.tex_x), CHUNK_SIZE as f32);
+        assert_eq!(span(|v| v.shape_vert.tex_y), CHUNK_SIZE as f32);
+    }
+
+    /** Площадь треугольников по направлениям нормали (в порядке сторон) и пары (материал, свет) их вершин */
+    fn sides(vertices: &[Vertex], indices: &[u32]) -> Vec<(f32, Vec<(i32, [u32; 3])>)> {
+        let mut sides = vec![(0.0, vec![]); 6];
+        for triangle in indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| &vertices[triangle[i] as usize]);
+            let (e1, e2) = (b.shape_vert.pos - a.shape_vert.pos, c.shape_vert.pos - a.shape_vert.pos);
+            let cross = Vec3::new(
+                e1.y() * e2.z() - e1.z() * e2.y(),
+                e1.z() * e2.x() - e1.x() * e2.z(),
+                e1.x() * e2.y() - e1.y() * e2.x(),
+            );
+            let normal = a.shape_vert.normal;
+            let side = [normal.x(), -normal.x(), normal.y(), -normal.y(), normal.z(), -normal.z()]
+                .iter().position(|&n| n > 0.5).unwrap();
+            sides[side].0 += cross.len() / 2.0;
+            for vertex in [a, b, c] {
+                sides[side].1.push((vertex.material_id, vertex.light.map(f32::to_bits)));
+            }
+        }
+        for side in sides.iter_mut() {
+            side.1.sort();
+            side.1.dedup();
+        }
+        sides
+    }
+}
@@ -0,0 +1,461 @@
+use std::collections::VecDeque;
+use crate::game::{chunk_pos_of, BlockData, BlockFace, BlockId, BlockModel, BlockState, Chunk, ChunkPos, Game, RenderLayer, CHUNK_SIZE, CHUNK_VOLUME};
+
+/** Наибольший уровень освещенности */
+pub const MAX_LIGHT: u8 = 15;
+
+/** Яркость по уровню освещенности. Каждый уровень темнее предыдущего на 20% */
+pub fn light_brightness(level: u8) -> f32 {
+    0.8f32.powi((MAX_LIGHT - level.min(MAX_LIGHT)) as i32)
+}
+
+/** Источник света. Оба уровня хранятся в одном байте: младшие 4 бита - свет от блоков, старшие - от неба */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    Block,
+    Sky,
+}
+impl LightChannel {
+    pub const ALL: [LightChannel; 2] = [LightChannel::Block, LightChannel::Sky];
+
+    /** Уровень этого источника из общего байта освещенности */
+    pub fn get(self, light: u8) -> u8 {
+        match self {
+            LightChannel::Block => light & 0x0F,
+            LightChannel::Sky   => light >> 4,
+        }
+    }
+    /** Общий байт освещенности с замененным уровнем этого источника */
+    pub fn with(self, light: u8, level: u8) -> u8 {
+        match self {
+            LightChannel::Block => (light & 0xF0) | (level & 0x0F),
+            LightChannel::Sky   => (light & 0x0F) | (level << 4),
+        }
+    }
+}
+
+/** Клетка мира и уровень света в ней */
+type LightNode = (i32, i32, i32, u8);
+
+/** Непрозрачен ли блок (см. Game::is_opaque). Не требует Game, поэтому подходит для фоновых потоков */
+pub fn is_opaque_block(blocks_data: &[BlockData], models_data: &[BlockModel], block: BlockId, state: BlockState) -> bool {
+    if block == 0 { return false; }
+    let data = &blocks_data[block as usize];
+    data.render_layer == RenderLayer::Opaque && models_data[data.variant(state).model_id].solid_faces().0 & 0b00111111 == 0b00111111
+}
+
+impl Chunk {
+    /** Свет чанка без учета соседей: над чанком открытое небо, свет не выходит за его границы.
+        Считается в фоновых потоках для сгенерированных чанков, свет соседей добавляет Game::light_chunk_borders.
+        Пока чанк сверху не загружен, тени от него нет: верхний загруженный слой чанков (см. STREAM_VERTICAL_DIST)
+        освещен небом, даже если глубоко под землей. Тень появляется, когда загрузится чанк сверху */
+    pub fn light_local(&mut self, blocks_data: &[BlockData], models_data: &[BlockModel]) {
+        let chs = CHUNK_SIZE as i32;
+        let transparent: Vec<bool> = (0..CHUNK_VOLUME)
+            .map(|id| !is_opaque_block(blocks_data, models_data, self.blocks.get(id), self.states.get(id)))
+            .collect();
+        let mut light = vec![0u8; CHUNK_VOLUME];
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+
+        //Прямой свет неба по столбцам и светящиеся блоки
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                for z in (0..CHUNK_SIZE).rev() {
+                    let id = Chunk::pos_id(x, y, z);
+                    if !transparent[id] { break; }
+                    light[id] = LightChannel::Sky.with(light[id], MAX_LIGHT);
+                    sky.push_back(id);
+                }
+            }
+        }
+        for (id, cell) in light.iter_mut().enumerate() {
+            let emission = blocks_data[self.blocks.get(id) as usize].emission.min(MAX_LIGHT);
+            if emission > 0 {
+                *cell = LightChannel::Block.with(*cell, emission);
+                block.push_back(id);
+            }
+        }
+
+        for (channel, mut queue) in [(LightChannel::Sky, sky), (LightChannel::Block, block)] {
+            while let Some(id) = queue.pop_front() {
+                let level = channel.get(light[id]);
+                if level <= 1 { continue; }
+                let (x, y, z) = ((id % CHUNK_SIZE) as i32, ((id / CHUNK_SIZE) % CHUNK_SIZE) as i32, (id / (CHUNK_SIZE * CHUNK_SIZE)) as i32);
+                for face in BlockFace::ALL {
+                    let (dx, dy, dz) = face.offset();
+                    let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                    if nx < 0 || ny < 0 || nz < 0 || nx >= chs || ny >= chs || nz >= chs { continue; }
+                    let neighbour = Chunk::pos_id(nx as usize, ny as usize, nz as usize);
+                    let new_level = Game::spread_level(channel, level, face);
+                    if transparent[neighbour] && channel.get(light[neighbour]) < new_level {
+                        light[neighbour] = channel.with(light[neighbour], new_level);
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+        }
+
+        self.clear_light();
+        for (id, cell) in light.into_iter().enumerate() {
+            if cell != 0 { self.light.set(id, cell); }
+        }
+    }
+}
+
+impl Game {
+    /** Освещенность (оба уровня) по мировым координатам. None, если чанк не загружен */
+    pub fn light_at(&self, x: i32, y: i32, z: i32) -> Option<u8> {
+        let chs = CHUNK_SIZE as i32;
+        let chunk = self.chunk(chunk_pos_of(x, y, z))?;
+        Some(chunk.light(x.rem_euclid(chs), y.rem_euclid(chs), z.rem_euclid(chs)))
+    }
+    /** Уровень освещенности от источника channel по мировым координатам. None, если чанк не загружен */
+    pub fn light_level(&self, channel: LightChannel, x: i32, y: i32, z: i32) -> Option<u8> {
+        self.light_at(x, y, z).map(|light| channel.get(light))
+    }
+
+    fn set_light_level(&mut self, channel: LightChannel, x: i32, y: i32, z: i32, level: u8) {
+        let chs = CHUNK_SIZE as i32;
+        let (lx, ly, lz) = (x.rem_euclid(chs), y.rem_euclid(chs), z.rem_euclid(chs));
+        let pos = chunk_pos_of(x, y, z);
+        let changed = match self.chunks.get_mut(pos) {
+            Some(chunk) => {
+                let light = chunk.light(lx, ly, lz);
+                chunk.set_light(lx, ly, lz, channel.with(light, level));
+                channel.get(light) != level
+            }
+            None => false,
+        };
+        //Свет блока на границе попадает в окрестность соседних чанков
+        if changed {
+            self.dirty.insert(pos);
+            self.mark_halo_neighbours(x, y, z);
+        }
+    }
+
+    /** Свет, испускаемый блоком */
+    pub fn block_emission(&self, block: BlockId) -> u8 {
+        self.blocks[block as usize].emission.min(MAX_LIGHT)
+    }
+
+    /** Уровень, с которым свет переходит из клетки с уровнем level в соседнюю в направлении face.
+        Свет неба полной яркости идет вниз без ослабления */
+    fn spread_level(channel: LightChannel, level: u8, face: BlockFace) -> u8 {
+        if channel == LightChannel::Sky && level == MAX_LIGHT && face == BlockFace::NZ {
+            MAX_LIGHT
+        } else {
+            level.saturating_sub(1)
+        }
+    }
+
+    /** Пропускает ли клетка свет. Незагруженные чанки свет не пропускают */
+    fn is_transparent_at(&self, x: i32, y: i32, z: i32) -> bool {
+        match self.block_at(x, y, z) {
+            Some((block, state)) => !self.is_opaque(block, state),
+            None => false,
+        }
+    }
+
+    /** Распространение света (поиск в ширину) из клеток очереди, уровень берется из самих клеток */
+    fn spread_light(&mut self, channel: LightChannel, mut queue: VecDeque<(i32, i32, i32)>) {
+        while let Some((x, y, z)) = queue.pop_front() {
+            let level = match self.light_level(channel, x, y, z) {
+                Some(level) if level > 1 => level,
+                _ => continue,
+            };
+            for face in BlockFace::ALL {
+                let (dx, dy, dz) = face.offset();
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let new_level = Self::spread_level(channel, level, face);
+                match self.light_level(channel, nx, ny, nz) {
+                    Some(old) if old < new_level && self.is_transparent_at(nx, ny, nz) => {
+                        self.set_light_level(channel, nx, ny, nz, new_level);
+                        queue.push_back((nx, ny, nz));
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    /** Удаление света, пришедшего из клеток очереди (их уровень уже обнулен, в очереди - прежний уровень).
+        Возвращает клетки на границе затемненной области, из которых свет нужно распространить заново */
+    fn remove_light(&mut self, channel: LightChannel, mut queue: VecDeque<LightNode>) -> VecDeque<(i32, i32, i32)> {
+        let mut relight = VecDeque::new();
+        while let Some((x, y, z, level)) = queue.pop_front() {
+            for face in BlockFace::ALL {
+                let (dx, dy, dz) = face.offset();
+                let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                let neighbour = match self.light_level(channel, nx, ny, nz) {
+                    Some(neighbour) if neighbour > 0 => neighbour,
+                    _ => continue,
+                };
+                //Свет соседа пришел отсюда - гасим и его, иначе сосед освещен другим путем
+                let sky_down = channel == LightChannel::Sky && face == BlockFace::NZ && level == MAX_LIGHT && neighbour == MAX_LIGHT;
+                if neighbour < level || sky_down {
+                    self.set_light_level(channel, nx, ny, nz, 0);
+                    queue.push_back((nx, ny, nz, neighbour));
+                    //Светящийся блок продолжает светить сам
+                    if channel == LightChannel::Block {
+                        if let Some((block, _)) = self.block_at(nx, ny, nz) {
+                            let emission = self.block_emission(block);
+                            if emission > 0 {
+                                self.set_light_level(channel, nx, ny, nz, emission);
+                                relight.push_back((nx, ny, nz));
+                            }
+                        }
+                    }
+                } else {
+                    relight.push_back((nx, ny, nz));
+                }
+            }
+        }
+        relight
+    }
+
+    /** Пересчитать свет вокруг блока x, y, z после его замены */
+    pub(super) fn update_light(&mut self, x: i32, y: i32, z: i32) {
+        let (block, state) = match self.block_at(x, y, z) {
+            Some(block) => block,
+            None => return,
+        };
+        let opaque = self.is_opaque(block, state);
+        let emission = self.block_emission(block);
+
+        for channel in LightChannel::ALL {
+            let old = self.light_level(channel, x, y, z).unwrap_or(0);
+            let mut relight = VecDeque::new();
+            if old > 0 {
+                self.set_light_level(channel, x, y, z, 0);
+                relight = self.remove_light(channel, VecDeque::from([(x, y, z, old)]));
+            }
+            if channel == LightChannel::Block && emission > 0 {
+                self.set_light_level(channel, x, y, z, emission);
+                relight.push_back((x, y, z));
+            }
+            //В прозрачную клетку свет заходит от соседей
+            if !opaque {
+                for face in BlockFace::ALL {
+                    let (dx, dy, dz) = face.offset();
+                    relight.push_back((x + dx, y + dy, z + dz));
+                }
+            }
+            self.spread_light(channel, relight);
+        }
+    }
+
+    /** Рассчитать свет только что загруженного чанка: свет внутри него и обмен светом с соседями */
+    pub(super) fn light_chunk(&mut self, pos: ChunkPos) {
+        match self.chunks.get_mut(pos) {
+            Some(chunk) => chunk.light_local(&self.blocks, &self.models),
+            None => return,
+        }
+        self.light_chunk_borders(pos);
+    }
+
+    /** Согласовать свет чанка, рассчитанный без соседей (Chunk::light_local), с уже загруженными соседями:
+        тень от чанка сверху, тень на чанк снизу и свет, проходящий через границы в обе стороны */
+    pub(super) fn light_chunk_borders(&mut self, pos: ChunkPos) {
+        let chs = CHUNK_SIZE as i32;
+        let (ox, oy, oz) = (pos.0 * chs, pos.1 * chs, pos.2 * chs);
+        if !self.chunks.contains(pos) { return; }
+        let mut sky = VecDeque::new();
+        let mut block = VecDeque::new();
+
+        //Чанк сверху загружен и закрывает небо: столбцы, освещенные напрямую, гасятся
+        let mut shadowed = vec![];
+        for x in 0..chs {
+            for y in 0..chs {
+                let (wx, wy) = (ox + x, oy + y);
+                let above = self.light_level(LightChannel::Sky, wx, wy, oz + chs).unwrap_or(MAX_LIGHT);
+                if above < MAX_LIGHT && self.light_level(LightChannel::Sky, wx, wy, oz + chs - 1) == Some(MAX_LIGHT) {
+                    shadowed.push((wx, wy, oz + chs - 1, MAX_LIGHT));
+                }
+            }
+        }
+        self.shade_sky(shadowed, &mut sky);
+        //Столбец в чанке снизу больше не освещен небом напрямую
+        let mut shadowed_below = vec![];
+        for x in 0..chs {
+            for y in 0..chs {
+                let (wx, wy) = (ox + x, oy + y);
+                if self.light_level(LightChannel::Sky, wx, wy, oz) != Some(MAX_LIGHT) &&
+                    self.light_level(LightChannel::Sky, wx, wy, oz - 1) == Some(MAX_LIGHT) {
+                    shadowed_below.push((wx, wy, oz - 1, MAX_LIGHT));
+                }
+            }
+        }
+        self.shade_sky(shadowed_below, &mut sky);
+
+        //Свет через границы: клетки по обе стороны каждой стороны чанка
+        for face in BlockFace::ALL {
+            for a in 0..chs {
+                for b in 0..chs {
+                    let (x, y, z) = match face {
+                        BlockFace::PX => (chs, a, b),
+                        BlockFace::NX => (-1, a, b),
+                        BlockFace::PY => (a, chs, b),
+                        BlockFace::NY => (a, -1, b),
+                        BlockFace::PZ => (a, b, chs),
+                        BlockFace::NZ => (a, b, -1),
+                    };
+                    let inside = (x.clamp(0, chs - 1), y.clamp(0, chs - 1), z.clamp(0, chs - 1));
+                    for (x, y, z) in [(ox + x, oy + y, oz + z), (ox + inside.0, oy + inside.1, oz + inside.2)] {
+                        if self.light_level(LightChannel::Sky, x, y, z).unwrap_or(0) > 0 { sky.push_back((x, y, z)); }
+                        if self.light_level(LightChannel::Block, x, y, z).unwrap_or(0) > 0 { block.push_back((x, y, z)); }
+                    }
+                }
+            }
+        }
+
+        self.spread_light(LightChannel::Sky, sky);
+        self.spread_light(LightChannel::Block, block);
+    }
+
+    /** Погасить прямой свет неба в клетках nodes и все, что от него зависело.
+        Клетки, из которых свет нужно распространить заново, добавляются в relight */
+    fn shade_sky(&mut self, nodes: Vec<LightNode>, relight: &mut VecDeque<(i32, i32, i32)>) {
+        if nodes.is_empty() { return; }
+        for (x, y, z, _) in nodes.iter() {
+            self.set_light_level(LightChannel::Sky, *x, *y, *z, 0);
+        }
+        relight.extend(self.remove_light(LightChannel::Sky, nodes.into()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::test_game;
+    use super::*;
+
+    const STONE: BlockId = 3;
+
+    fn lamp(game: &Game) -> BlockId {
+        game.get_block_id("lamp".into()).unwrap() as BlockId
+    }
+
+    /** Освещенность всех клеток чанка */
+    fn chunk_light(chunk: &Chunk) -> Vec<u8> {
+        let chs = CHUNK_SIZE as i32;
+        (0..chs).flat_map(|z| (0..chs).flat_map(move |y| (0..chs).map(move |x| (x, y, z))))
+            .map(|(x, y, z)| chunk.light(x, y, z))
+            .collect()
+    }
+
+    /** Свет чанка, пересчитанный с нуля. Для чанка без соседей должен совпадать с обновленным по шагам */
+    fn fresh_light(game: &Game, pos: ChunkPos) -> Vec<u8> {
+        let mut chunk = game.chunk(pos).unwrap().clone();
+        chunk.light_local(&game.blocks, &game.models);
+        chunk_light(&chunk)
+    }
+
+    /** Пустой чанк с каменным слоем z = 0 по всей площади */
+    fn chunk_with_floor(game: &Game, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        let solid_faces = game.block_solid_faces(STONE, 0);
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                chunk.set_block(STONE, x, y, 0, solid_faces);
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn lamp_is_placed_and_removed() {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        let lamp = lamp(&game);
+
+        game.set_block(lamp, 8, 16, 16);
+        game.set_block(lamp, 20, 16, 16);
+        assert_eq!(game.light_level(LightChannel::Block, 8, 16, 16), Some(MAX_LIGHT));
+        assert_eq!(game.light_level(LightChannel::Block, 14, 16, 16), Some(MAX_LIGHT - 6));
+        assert_eq!(game.light_level(LightChannel::Block, 26, 16, 16), Some(MAX_LIGHT - 6));
+
+        //Без второй лампы ее клетки освещает только первая
+        game.set_block(0, 20, 16, 16);
+        assert_eq!(game.light_level(LightChannel::Block, 20, 16, 16), Some(MAX_LIGHT - 12));
+        assert_eq!(game.light_level(LightChannel::Block, 14, 16, 16), Some(MAX_LIGHT - 6));
+        assert_eq!(game.light_level(LightChannel::Block, 26, 16, 16), Some(0));
+        assert_eq!(chunk_light(game.chunk((0, 0, 0)).unwrap()), fresh_light(&game, (0, 0, 0)));
+
+        game.set_block(0, 8, 16, 16);
+        let chunk = game.chunk((0, 0, 0)).unwrap();
+        assert!(chunk_light(chunk).iter().all(|&light| LightChannel::Block.get(light) == 0));
+        assert_eq!(chunk_light(chunk), fresh_light(&game, (0, 0, 0)));
+    }
+
+    #[test]
+    fn opaque_block_shades_column() {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        assert_eq!(game.light_level(LightChannel::Sky, 5, 5, 0), Some(MAX_LIGHT));
+
+        //Под блоком прямого света неба нет, свет приходит только сбоку
+        game.set_block(STONE, 5, 5, 20);
+        assert_eq!(game.light_level(LightChannel::Sky, 5, 5, 21), Some(MAX_LIGHT));
+        assert_eq!(game.light_level(LightChannel::Sky, 5, 5, 20), Some(0));
+        for z in 0..20 {
+            assert_eq!(game.light_level(LightChannel::Sky, 5, 5, z), Some(MAX_LIGHT - 1), "z = {}", z);
+        }
+        assert_eq!(chunk_light(game.chunk((0, 0, 0)).unwrap()), fresh_light(&game, (0, 0, 0)));
+
+        game.set_block(0, 5, 5, 20);
+        for z in 0..CHUNK_SIZE as i32 {
+            assert_eq!(game.light_level(LightChannel::Sky, 5, 5, z), Some(MAX_LIGHT), "z = {}", z);
+        }
+        assert_eq!(chunk_light(game.chunk((0, 0, 0)).unwrap()), fresh_light(&game, (0, 0, 0)));
+    }
+
+    #[test]
+    fn light_crosses_chunk_border() {
+        let game = test_game();
+        let lamp = lamp(&game);
+        //Лампа у границы в чанке (0, 0, 0), соседний чанк (1, 0, 0) закрыт от неба крышей z = 31
+        let mut lit = Chunk::empty(0, 0, 0);
+        lit.set_block(lamp, 30, 16, 16, game.block_solid_faces(lamp, 0));
+        let mut roofed = Chunk::empty(1, 0, 0);
+        let solid_faces = game.block_solid_faces(STONE, 0);
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                roofed.set_block(STONE, x, y, CHUNK_SIZE as i32 - 1, solid_faces);
+            }
+        }
+
+        let mut results = vec![];
+        for order in [[lit.clone(), roofed.clone()], [roofed.clone(), lit.clone()]] {
+            let mut game = test_game();
+            for chunk in order { game.add_chunk(chunk); }
+            assert_eq!(game.light_level(LightChannel::Block, 33, 16, 16), Some(MAX_LIGHT - 3));
+            //Под крышей свет неба заходит только через границу
+            assert_eq!(game.light_level(LightChannel::Sky, 32, 16, 16), Some(MAX_LIGHT - 1));
+            assert_eq!(game.light_level(LightChannel::Sky, 40, 16, 16), Some(MAX_LIGHT - 9));
+            results.push([(0, 0, 0), (1, 0, 0)].map(|pos| chunk_light(game.chunk(pos).unwrap())));
+        }
+        assert!(results[0] == results[1]);
+    }
+
+    #[test]
+    fn top_loaded_chunk_is_under_open_sky() {
+        //Пока чанк сверху не загружен, над чанком считается открытое небо
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, -1));
+        assert_eq!(game.light_level(LightChannel::Sky, 5, 5, -20), Some(MAX_LIGHT));
+
+        //Загруженный сверху чанк с полом закрывает небо, в каком бы порядке ни грузились чанки
+        let mut results = vec![];
+        for order in [[(0, 0, -1), (0, 0, 0)], [(0, 0, 0), (0, 0, -1)]] {
+            let mut game = test_game();
+            for pos in order {
+                let chunk = if pos.2 == 0 { chunk_with_floor(&game, pos) } else { Chunk::empty(pos.0, pos.1, pos.2) };
+                game.add_chunk(chunk);
+            }
+            let below = chunk_light(game.chunk((0, 0, -1)).unwrap());
+            assert!(below.iter().all(|&light| LightChannel::Sky.get(light) == 0));
+            results.push([(0, 0, -1), (0, 0, 0)].map(|pos| chunk_light(game.chunk(pos).unwrap())));
+        }
+        assert!(results[0] == results[1]);
+    }
+}
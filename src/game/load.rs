@@ -1,6 +1,395 @@
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::game::{get_first_word, BlockData, BlockFace, BlockModel, BlockProperty, BMShape, ModelOverride, RenderLayer, ShapeVertex, TextureOverride};
+use crate::resources::{self, FileFormat, Resources};
 
-pub struct BlockD {
-    name: String,
-    sides_texture: Vec<usize>,
+/** Ошибки загрузки и проверки реестра блоков */
+#[derive(Debug)]
+pub enum RegistryError {
+    Resource(resources::Error),
+    Parse(serde_json::Error),
+    /** Два блока с одинаковым названием */
+    DuplicateName(String),
+    /** Блок ссылается на несуществующую модель (блок, модель) */
+    UnknownModel(String, String),
+    /** Для текстуры нет ни одного изображения (блок, текстура) */
+    MissingTexture(String, String),
+    /** Количество текстур не совпадает с количеством групп модели (блок, нужно, указано) */
+    WrongTexturesCount(String, usize, usize),
+    /** Замена текстур или модели ссылается на несуществующее свойство или значение (блок, свойство, значение) */
+    UnknownPropertyValue(String, String, String),
+    /** Блока с таким названием нет */
+    UnknownBlock(String),
+    /** У блока нет такого состояния (блок, свойство, значение) */
+    UnknownState(String, String, String),
+    /** Две модели с одинаковым названием */
+    DuplicateModel(String),
+    /** Ошибка в описании модели (модель, описание ошибки) */
+    InvalidModel(String, String),
+    /** Модель поворачивают сразу несколько свойств блока (блок, свойство, свойство) */
+    MultipleRotations(String, String, String),
+}
+impl From<resources::Error> for RegistryError {
+    fn from(other: resources::Error) -> Self { RegistryError::Resource(other) }
+}
+impl From<serde_json::Error> for RegistryError {
+    fn from(other: serde_json::Error) -> Self { RegistryError::Parse(other) }
+}
 
-}
\ No newline at end of file
+/** Описание блока в файле реестра */
+#[derive(Clone, Debug, Deserialize)]
+pub struct BlockDefinition {
+    pub name: String,
+    pub model: String,
+    /** Названия текстур по группам модели. Одна текстура применяется ко всем группам */
+    #[serde(default)]
+    pub textures: Vec<String>,
+    #[serde(default)]
+    pub properties: Vec<PropertyDefinition>,
+    #[serde(default)]
+    pub texture_overrides: Vec<TextureOverrideDefinition>,
+    #[serde(default)]
+    pub model_overrides: Vec<ModelOverrideDefinition>,
+    /** Уровень испускаемого света, от 0 до 15 */
+    #[serde(default)]
+    pub emission: u8,
+    /** Слой отрисовки: "opaque" (по умолчанию), "cutout" или "translucent" */
+    #[serde(default)]
+    pub render_layer: RenderLayer,
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct PropertyDefinition {
+    pub name: String,
+    pub values: Vec<String>,
+}
+#[derive(Clone, Debug, Deserialize)]
+pub struct TextureOverrideDefinition {
+    pub property: String,
+    pub value: String,
+    pub textures: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ModelOverrideDefinition {
+    pub property: String,
+    pub value: String,
+    pub model: String,
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    blocks: Vec<BlockDefinition>,
+}
+
+/** Реестр блоков, загружаемый из файла (assets/blocks.json).
+    Номера блоков совпадают с порядком их описания в файле */
+pub struct BlockRegistry {
+    definitions: Vec<BlockDefinition>,
+    textures: Vec<String>,  //Названия всех используемых текстур в порядке первого упоминания
+}
+impl BlockRegistry {
+    pub fn load(res: &Resources, path: &str) -> Result<Self, RegistryError> {
+        Self::from_json(&res.load_string(path)?)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, RegistryError> {
+        let file: RegistryFile = serde_json::from_str(text)?;
+        Self::from_definitions(file.blocks)
+    }
+
+    pub fn from_definitions(definitions: Vec<BlockDefinition>) -> Result<Self, RegistryError> {
+        for (i, d) in definitions.iter().enumerate() {
+            if definitions[..i].iter().any(|other| other.name == d.name) {
+                return Err(RegistryError::DuplicateName(d.name.clone()));
+            }
+        }
+
+        let mut textures: Vec<String> = vec![];
+        for d in definitions.iter() {
+            let overrides = d.texture_overrides.iter().flat_map(|o| o.textures.iter());
+            for t in d.textures.iter().chain(overrides) {
+                if t != "nil" && !textures.contains(t) {
+                    textures.push(t.clone());
+                }
+            }
+        }
+
+        Ok(Self { definitions, textures })
+    }
+
+    pub fn definitions(&self) -> &Vec<BlockDefinition> { &self.definitions }
+
+    /** Названия текстур, которые нужно собрать в атлас (без "nil") */
+    pub fn texture_names(&self) -> &Vec<String> { &self.textures }
+
+    /** Номер текстуры в атласе. "nil" всегда нулевая */
+    pub fn texture_id(&self, name: &str) -> Option<u32> {
+        if name == "nil" { return Some(0); }
+        self.textures.iter().position(|t| t == name).map(|i| i as u32 + 1)
+    }
+
+    /** Проверить, что для каждой текстуры есть изображение в папке dir */
+    pub fn check_textures(&self, res: &Resources, dir: PathBuf) -> Result<(), RegistryError> {
+        let available: Vec<String> = res.get_all_images_list(dir).into_iter().map(get_first_word).collect();
+        self.check_textures_available(&available)
+    }
+    pub fn check_textures_available(&self, available: &[String]) -> Result<(), RegistryError> {
+        for d in self.definitions.iter() {
+            let overrides = d.texture_overrides.iter().flat_map(|o| o.textures.iter());
+            for t in d.textures.iter().chain(overrides) {
+                if t != "nil" && !available.contains(t) {
+                    return Err(RegistryError::MissingTexture(d.name.clone(), t.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /** Данные блоков по описаниям. find_model возвращает номер модели и количество ее групп по названию */
+    pub fn build_blocks<F>(&self, find_model: F) -> Result<Vec<BlockData>, RegistryError>
+        where F: Fn(&str) -> Option<(usize, usize)>
+    {
+        let mut result = Vec::with_capacity(self.definitions.len());
+        for d in self.definitions.iter() {
+            let (model_id, groups) = find_model(&d.model)
+                .ok_or_else(|| RegistryError::UnknownModel(d.name.clone(), d.model.clone()))?;
+
+            let properties: Vec<BlockProperty> = d.properties.iter()
+                .map(|p| BlockProperty::new(p.name.clone(), p.values.iter().map(|v| &v[..]).collect()))
+                .collect();
+
+            let mut texture_overrides = vec![];
+            for o in d.texture_overrides.iter() {
+                let known = properties.iter().any(|p| p.name == o.property && p.values.contains(&o.value));
+                if !known {
+                    return Err(RegistryError::UnknownPropertyValue(d.name.clone(), o.property.clone(), o.value.clone()));
+                }
+                texture_overrides.push(TextureOverride {
+                    property: o.property.clone(),
+                    value: o.value.clone(),
+                    textures: self.group_textures(d, &o.textures, groups)?,
+                });
+            }
+
+            //Текстуры задаются для основной модели, поэтому у заменяющей модели столько же групп
+            let mut model_overrides = vec![];
+            for o in d.model_overrides.iter() {
+                let known = properties.iter().any(|p| p.name == o.property && p.values.contains(&o.value));
+                if !known {
+                    return Err(RegistryError::UnknownPropertyValue(d.name.clone(), o.property.clone(), o.value.clone()));
+                }
+                let (override_id, override_groups) = find_model(&o.model)
+                    .ok_or_else(|| RegistryError::UnknownModel(d.name.clone(), o.model.clone()))?;
+                if override_groups != groups {
+                    return Err(RegistryError::InvalidModel(o.model.clone(),
+                        format!("{} groups, block {} expects {}", override_groups, d.name, groups)));
+                }
+                model_overrides.push(ModelOverride { property: o.property.clone(), value: o.value.clone(), model_id: override_id });
+            }
+
+            result.push(BlockData {
+                name: d.name.clone(),
+                model_id,
+                textures: self.group_textures(d, &d.textures, groups)?,
+                properties,
+                texture_overrides,
+                model_overrides,
+                variants: vec![],
+                emission: d.emission,
+                render_layer: d.render_layer,
+            });
+        }
+        Ok(result)
+    }
+
+    fn group_textures(&self, d: &BlockDefinition, names: &[String], groups: usize) -> Result<Vec<u32>, RegistryError> {
+        let ids: Vec<u32> = names.iter().map(|n| self.texture_id(n).unwrap_or(0)).collect();
+        match ids.len() {
+            1 => Ok(vec![ids[0]; groups]),
+            n if n == groups => Ok(ids),
+            n => Err(RegistryError::WrongTexturesCount(d.name.clone(), groups, n)),
+        }
+    }
+}
+
+/** Файл модели блока (json в assets/models). Повторяет структуру BlockModel:
+    группы фигур (по одной текстуре на группу), фигуры из вершин и треугольников */
+#[derive(Deserialize)]
+struct ModelFile {
+    /** По умолчанию - имя файла без расширения */
+    name: Option<String>,
+    /** Стороны, которые модель закрывает целиком */
+    #[serde(default)]
+    solid_faces: Vec<String>,
+    groups: Vec<Vec<ShapeDefinition>>,
+}
+#[derive(Deserialize)]
+struct ShapeDefinition {
+    /** Сторона блока, которой принадлежит фигура (как BMShape::set_face) */
+    face: Option<String>,
+    /** Дополнительные стороны, от закрытости которых зависит фигура (как BMShape::face) */
+    #[serde(default)]
+    overlap: Vec<String>,
+    vertices: Vec<VertexDefinition>,
+    indices: Vec<[u32; 3]>,
+}
+#[derive(Deserialize)]
+struct VertexDefinition {
+    pos: [f32; 3],
+    normal: [f32; 3],
+    uv: [f32; 2],
+}
+
+/** Загрузить все модели из папки (и подпапок) */
+pub fn load_block_models(res: &Resources, dir: PathBuf) -> Result<Vec<BlockModel>, RegistryError> {
+    let mut files = res.get_all_files_list(dir.clone(), FileFormat::Json);
+    files.sort();
+
+    let mut models = vec![];
+    for local_path in files {
+        let default_name = local_path.file_stem().unwrap().to_str().unwrap().to_string();
+        let text = res.load_string(dir.join(&local_path).to_str().unwrap())?;
+        models.push(parse_block_model(&text, default_name)?);
+    }
+    Ok(models)
+}
+
+/** Разобрать модель блока из json */
+pub fn parse_block_model(text: &str, default_name: String) -> Result<BlockModel, RegistryError> {
+    let file: ModelFile = serde_json::from_str(text)?;
+    let name = file.name.unwrap_or(default_name);
+    let invalid = |reason: String| RegistryError::InvalidModel(name.clone(), reason);
+    let parse_face = |face: &str| BlockFace::from_name(face).ok_or_else(|| invalid(format!("unknown face \"{}\"", face)));
+
+    let mut model = BlockModel::new(name.clone());
+    for face in file.solid_faces.iter() {
+        model.solid_face(parse_face(face)?);
+    }
+
+    for group in file.groups.iter() {
+        model.new_group();
+        for shape_def in group.iter() {
+            let mut shape = BMShape::new();
+            for v in shape_def.vertices.iter() {
+                shape.vertex(ShapeVertex::new(
+                    v.pos[0], v.pos[1], v.pos[2],
+                    v.normal[0], v.normal[1], v.normal[2],
+                    v.uv[0], v.uv[1]));
+            }
+            for i in shape_def.indices.iter() {
+                if i.iter().any(|id| *id as usize >= shape_def.vertices.len()) {
+                    return Err(invalid(format!("index {:?} is out of {} vertices", i, shape_def.vertices.len())));
+                }
+                shape.index((i[0], i[1], i[2]));
+            }
+            if let Some(face) = &shape_def.face {
+                shape.set_face(parse_face(face)?);
+            }
+            for face in shape_def.overlap.iter() {
+                shape.face(parse_face(face)?);
+            }
+            model.add(shape);
+        }
+    }
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Модели для build_blocks: номер и количество групп */
+    fn find_model(name: &str) -> Option<(usize, usize)> {
+        match name {
+            "empty" => Some((0, 0)),
+            "cube" => Some((1, 1)),
+            "grass_cube" => Some((2, 3)),
+            _ => None,
+        }
+    }
+
+    fn registry(blocks: &str) -> Result<BlockRegistry, RegistryError> {
+        BlockRegistry::from_json(&format!("{{ \"blocks\": [{}] }}", blocks))
+    }
+
+    #[test]
+    fn builds_blocks() {
+        let registry = registry(r#"
+            { "name": "air", "model": "empty" },
+            { "name": "grass", "model": "grass_cube", "textures": ["grass_top", "grass_side", "dirt"] },
+            { "name": "dirt", "model": "cube", "textures": ["dirt"] }"#).unwrap();
+        assert_eq!(registry.texture_names(), &vec!["grass_top".to_string(), "grass_side".into(), "dirt".into()]);
+        let blocks = registry.build_blocks(find_model).unwrap();
+        assert_eq!(blocks.iter().map(|b| &b.name[..]).collect::<Vec<_>>(), vec!["air", "grass", "dirt"]);
+        assert_eq!((blocks[1].model_id, &blocks[1].textures), (2, &vec![1, 2, 3]));
+        assert_eq!(blocks[2].textures, vec![3]);
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let res = registry(r#"{ "name": "dirt", "model": "cube" }, { "name": "dirt", "model": "cube" }"#);
+        assert!(matches!(res, Err(RegistryError::DuplicateName(name)) if name == "dirt"));
+    }
+
+    #[test]
+    fn unknown_model() {
+        let registry = registry(r#"{ "name": "dirt", "model": "cube", "textures": ["dirt"] }, { "name": "pipe", "model": "pipe" }"#).unwrap();
+        let res = registry.build_blocks(find_model);
+        assert!(matches!(res, Err(RegistryError::UnknownModel(block, model)) if block == "pipe" && model == "pipe"));
+    }
+
+    #[test]
+    fn missing_texture() {
+        let registry = registry(r#"{ "name": "dirt", "model": "cube", "textures": ["dirt"] },
+                                   { "name": "lamp", "model": "cube", "textures": ["lamp"] }"#).unwrap();
+        assert!(registry.check_textures_available(&["dirt".into(), "lamp".into()]).is_ok());
+        let res = registry.check_textures_available(&["dirt".into(), "stone".into()]);
+        assert!(matches!(res, Err(RegistryError::MissingTexture(block, texture)) if block == "lamp" && texture == "lamp"));
+    }
+
+    #[test]
+    fn wrong_textures_count() {
+        //Одна текстура подходит к любой модели, иначе нужно по текстуре на группу
+        let registry = registry(r#"{ "name": "grass", "model": "grass_cube", "textures": ["grass_top", "dirt"] }"#).unwrap();
+        let res = registry.build_blocks(find_model);
+        assert!(matches!(res, Err(RegistryError::WrongTexturesCount(block, 3, 2)) if block == "grass"));
+    }
+
+    #[test]
+    fn parses_block_model() {
+        let model = parse_block_model(r#"{
+            "solid_faces": ["nz"],
+            "groups": [[{
+                "face": "nz",
+                "vertices": [
+                    { "pos": [-0.5, -0.5, -0.5], "normal": [0, 0, -1], "uv": [0, 1] },
+                    { "pos": [0.5, -0.5, -0.5], "normal": [0, 0, -1], "uv": [1, 1] },
+                    { "pos": [0.5, 0.5, -0.5], "normal": [0, 0, -1], "uv": [1, 0] }
+                ],
+                "indices": [[2, 1, 0]]
+            }]]
+        }"#, "plate".into()).unwrap();
+        assert_eq!(model.name, "plate");
+        assert_eq!(model.shapes.len(), 1);
+        assert_eq!(model.solid_faces().0, 1 << u8::from(BlockFace::NZ));
+    }
+
+    #[test]
+    fn rejects_malformed_model() {
+        let parse = |text: &str| parse_block_model(text, "broken".into());
+        //Не json и json не той структуры
+        assert!(matches!(parse(r#"{ "groups": [[{ "vertices": [ }]] }"#), Err(RegistryError::Parse(_))));
+        assert!(matches!(parse(r#"{ "solid_faces": ["nz"] }"#), Err(RegistryError::Parse(_))));
+        assert!(matches!(parse(r#"{ "groups": [[{ "vertices": [{ "pos": [0, 0], "normal": [0, 0, 1], "uv": [0, 0] }], "indices": [] }]] }"#),
+            Err(RegistryError::Parse(_))));
+
+        //Json верный, но модель - нет
+        let vertex = r#"{ "pos": [0, 0, 0], "normal": [0, 0, 1], "uv": [0, 0] }"#;
+        let res = parse(r#"{ "solid_faces": ["up"], "groups": [] }"#);
+        assert!(matches!(res, Err(RegistryError::InvalidModel(name, _)) if name == "broken"));
+        let res = parse(&format!(r#"{{ "groups": [[{{ "face": "pz", "vertices": [{0}, {0}, {0}], "indices": [[0, 1, 3]] }}]] }}"#, vertex));
+        assert!(matches!(res, Err(RegistryError::InvalidModel(_, _))));
+        let res = parse(&format!(r#"{{ "groups": [[{{ "face": "top", "vertices": [{0}], "indices": [] }}]] }}"#, vertex));
+        assert!(matches!(res, Err(RegistryError::InvalidModel(_, _))));
+    }
+}
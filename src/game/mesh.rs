@@ -0,0 +1,194 @@
+use serde::Deserialize;
+use crate::game::{texture_model, AttribType, Vertex};
+use crate::mat::Vec3;
+use crate::Model;
+
+/** Как рисуется блок */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderLayer {
+    /** Непрозрачный, в проходе геометрии */
+    #[default]
+    Opaque,
+    /** С дырами (листва): в проходе геометрии, почти прозрачные точки текстуры отбрасываются */
+    Cutout,
+    /** Полупрозрачный (стекло, вода): отдельная модель, рисуется после прохода освещения от дальних треугольников к ближним */
+    Translucent,
+}
+
+/** Вершины и индексы модели (обычно чанка) в памяти процессора.
+    Строятся без OpenGL, поэтому их можно собирать в любом потоке. На видеокарту загружаются отдельно (upload) */
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+impl ChunkMesh {
+    pub fn new() -> Self { Self::default() }
+
+    /** Нет ни одного треугольника */
+    pub fn is_empty(&self) -> bool { self.indices.is_empty() }
+    /** Количество треугольников */
+    pub fn triangles_count(&self) -> usize { self.indices.len() / 3 }
+
+    /** Загрузить на видеокарту. Нужен текущий контекст OpenGL */
+    pub fn upload(&self) -> Model {
+        texture_model(&self.vertices, &self.indices, &vertex_attributes())
+    }
+
+    /** Индексы с треугольниками, отсортированными от дальних к ближним до точки eye (в координатах модели) */
+    pub fn sorted_indices(&self, eye: Vec3) -> Vec<u32> {
+        let mut triangles: Vec<(f32, &[u32])> = self.indices.chunks_exact(3)
+            .map(|t| {
+                let center = t.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, i| sum + self.vertices[*i as usize].shape_vert.pos) / 3.0;
+                let d = center - eye;
+                (d * d, t)
+            })
+            .collect();
+        triangles.sort_by(|a, b| b.0.total_cmp(&a.0));
+        triangles.iter().flat_map(|(_, t)| t.iter().copied()).collect()
+    }
+}
+
+/** Модели чанка по слоям отрисовки */
+#[derive(Clone, Debug, Default)]
+pub struct ChunkMeshes {
+    pub opaque: ChunkMesh,
+    pub cutout: ChunkMesh,
+    pub translucent: ChunkMesh,
+}
+impl ChunkMeshes {
+    pub fn new() -> Self { Self::default() }
+
+    pub fn layer(&self, layer: RenderLayer) -> &ChunkMesh {
+        match layer {
+            RenderLayer::Opaque      => &self.opaque,
+            RenderLayer::Cutout      => &self.cutout,
+            RenderLayer::Translucent => &self.translucent,
+        }
+    }
+    pub fn layer_mut(&mut self, layer: RenderLayer) -> &mut ChunkMesh {
+        match layer {
+            RenderLayer::Opaque      => &mut self.opaque,
+            RenderLayer::Cutout      => &mut self.cutout,
+            RenderLayer::Translucent => &mut self.translucent,
+        }
+    }
+    pub fn is_empty(&self) -> bool { self.opaque.is_empty() && self.cutout.is_empty() && self.translucent.is_empty() }
+
+    /** Загрузить непустые слои на видеокарту. Нужен текущий контекст OpenGL */
+    pub fn upload(self) -> ChunkModel {
+        let upload = |mesh: &ChunkMesh| if mesh.is_empty() { None } else { Some(mesh.upload()) };
+        ChunkModel {
+            opaque: upload(&self.opaque),
+            cutout: upload(&self.cutout),
+            translucent: upload(&self.translucent).map(|model| TranslucentModel { model, mesh: self.translucent, sorted_for: None }),
+        }
+    }
+}
+
+/** Модели чанка на видеокарте. Пустые слои не загружаются */
+pub struct ChunkModel {
+    pub opaque: Option<Model>,
+    pub cutout: Option<Model>,
+    pub translucent: Option<TranslucentModel>,
+}
+
+/** Модель полупрозрачного слоя. Копия вершин остается в памяти, чтобы сортировать треугольники при движении камеры */
+pub struct TranslucentModel {
+    model: Model,
+    mesh: ChunkMesh,
+    /** Блок (в координатах модели), из которого смотрела камера при последней сортировке */
+    sorted_for: Option<(i32, i32, i32)>,
+}
+impl TranslucentModel {
+    pub fn model(&self) -> &Model { &self.model }
+
+    /** Отсортировать треугольники от дальних к ближним до точки eye (в координатах модели).
+        Пока камера в том же блоке, порядок не пересчитывается */
+    pub fn sort(&mut self, eye: Vec3) {
+        let block = (eye.x().round() as i32, eye.y().round() as i32, eye.z().round() as i32);
+        if self.sorted_for == Some(block) { return; }
+        self.sorted_for = Some(block);
+        self.model.update_indices(&self.mesh.sorted_indices(eye));
+    }
+}
+
+/** Атрибуты вершины (Vertex) в порядке ее полей:
+    позиция, нормаль, касательные X и Y, координаты текстуры, номер материала, случайное число, освещение (блоки, небо, AO) */
+pub fn vertex_attributes() -> Vec<AttribType> {
+    use AttribType::*;
+    vec![Vec3, Vec3, Vec3, Vec3, Vec2, Int, Int, Vec3]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, BlockId, Chunk, Game};
+    use super::*;
+
+    //Пустой чанк в (0, 0, 0) с блоками blocks (мировые координаты) и его модель
+    fn build(blocks: &[(&str, i32, i32, i32)]) -> ChunkMeshes {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        for (name, x, y, z) in blocks {
+            let id = game.get_block_id((*name).into()).unwrap() as BlockId;
+            game.set_block(id, *x, *y, *z);
+        }
+        mesh_of(&game)
+    }
+    fn mesh_of(game: &Game) -> ChunkMeshes {
+        let halo = game.chunk_halo((0, 0, 0));
+        game.chunk((0, 0, 0)).unwrap().build_mesh(&game.blocks, &game.models, game.atlas.textures(), &halo)
+    }
+
+    #[test]
+    fn empty_chunk() {
+        assert!(build(&[]).is_empty());
+    }
+
+    #[test]
+    fn lone_cube() {
+        let meshes = build(&[("stone", 5, 5, 5)]);
+        //6 сторон по 4 вершины и 2 треугольника
+        assert_eq!(meshes.opaque.vertices.len(), 24);
+        assert_eq!(meshes.opaque.indices.len(), 36);
+        assert!(meshes.cutout.is_empty() && meshes.translucent.is_empty());
+    }
+
+    #[test]
+    fn face_between_cubes_is_culled() {
+        //Разные блоки, чтобы стороны не объединились в одну
+        let meshes = build(&[("stone", 5, 5, 5), ("dirt", 6, 5, 5)]);
+        assert_eq!(meshes.opaque.vertices.len(), 2 * 5 * 4);
+        assert_eq!(meshes.opaque.indices.len(), 2 * 5 * 6);
+        //Ни одна вершина не лежит на общей стороне x = 5.5
+        assert!(meshes.opaque.vertices.iter().all(|v| {
+            let p = v.shape_vert.pos;
+            (p.x() - 5.5).abs() > 1e-4 || (p.y() - 5.0).abs() >= 0.5 || (p.z() - 5.0).abs() >= 0.5
+        }));
+    }
+
+    #[test]
+    fn sorted_back_to_front() {
+        let meshes = build(&[("glass", 2, 5, 5), ("glass", 9, 5, 5), ("glass", 20, 7, 3)]);
+        let mesh = &meshes.translucent;
+        assert_eq!(mesh.triangles_count(), 3 * 12);
+        let eye = Vec3::new(0.0, 0.0, 0.0);
+        let sorted = mesh.sorted_indices(eye);
+        assert_eq!(sorted.len(), mesh.indices.len());
+        let mut all = sorted.clone();
+        all.sort();
+        let mut expected = mesh.indices.clone();
+        expected.sort();
+        assert_eq!(all, expected);
+
+        let distances: Vec<f32> = sorted.chunks_exact(3).map(|t| {
+            let center = t.iter().fold(Vec3::new(0.0, 0.0, 0.0), |sum, i| sum + mesh.vertices[*i as usize].shape_vert.pos) / 3.0;
+            let d = center - eye;
+            d * d
+        }).collect();
+        assert!(distances.windows(2).all(|w| w[0] >= w[1]), "{distances:?}");
+        //Первыми рисуются треугольники дальнего блока
+        assert!(distances[0] > 19.0 * 19.0);
+    }
+}
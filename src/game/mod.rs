@@ -4,22 +4,59 @@ mod traits;
 mod utils;
 mod atlas;
 mod load;
+mod chunk_map;
+mod palette;
+mod block_state;
+mod region;
+mod noise;
+mod generator;
+mod features;
+mod raycast;
+mod physics;
+mod ao;
+mod light;
+mod greedy;
+mod mesh;
+mod workers;
+mod streaming;
+mod visibility;
+mod coverage;
 
 pub use utils::*;
+pub use load::*;
 pub use atlas::*;
-
+pub use chunk_map::*;
+pub use palette::*;
+pub use block_state::*;
+pub use region::*;
+pub use noise::*;
+pub use generator::*;
+pub use features::*;
+pub use raycast::*;
+pub use physics::*;
+pub use ao::*;
+pub use light::*;
+pub use mesh::*;
+pub use workers::*;
+pub use streaming::*;
+pub use visibility::*;
+
+use std::collections::{HashMap, HashSet};
 use std::f64::consts::PI;
 use std::path::PathBuf;
-use crate::{Input, mat, rgl};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use crate::{Input, mat};
 use crate::mat::{Vec3};
 use crate::resources::Resources;
-use crate::rgl::Model;
 
 
-const MAX_GRID_SIZE: usize = 128*128*128;
 const CHUNK_SIZE: usize     = 32;
 const CHUNK_VOLUME: usize   = CHUNK_SIZE.pow(3);
 
+/** Номер типа блока */
+pub type BlockId = u16;
+
 
 pub struct Player {
     pub x: f64,
@@ -28,14 +65,26 @@ pub struct Player {
 
     pub ang_vert: f64,
     pub ang_horz: f64,
+
+    /** Скорость в блоках в секунду (в полете не используется) */
+    pub velocity: [f64; 3],
+    pub on_ground: bool,
+    /** Свободный полет без гравитации и столкновений */
+    pub fly: bool,
 }
 impl Player {
     pub fn new() -> Self {
         Player{
             x: 0.0, y: 0.0, z: 0.0,
             ang_horz: 0.0, ang_vert: 0.0,
+            velocity: [0.0; 3], on_ground: false, fly: false,
         }
     }
+    /** Единичный вектор направления взгляда */
+    pub fn look_direction(&self) -> Vec3 {
+        let (az, ax) = (self.ang_horz, self.ang_vert);
+        Vec3::new((az.sin() * ax.cos()) as f32, (az.cos() * ax.cos()) as f32, ax.sin() as f32)
+    }
     pub fn move_plr(&mut self, forw: f64, right: f64, up: f64) {
         let az = self.ang_horz;        //Угол, указывающий прямо
         let azr = self.ang_horz - PI / 2.0;     //Угол вправо
@@ -44,19 +93,19 @@ impl Player {
         self.y += az.cos() * ax.cos() * forw - azr.cos() * right;
         self.z += ax.sin() * forw + up;
     }
-    pub fn move_by_input(&mut self, inp: &Input, step: f64) {
-        let vel = if inp.key_pressed(sdl2::keyboard::Keycode::LShift) { 80.0 } else { 4.00 };
-        let step = step * vel * 1.0;
-        let dx = if inp.key_pressed(sdl2::keyboard::Keycode::W) { step } else { 0.0 };
-        let dx = if inp.key_pressed(sdl2::keyboard::Keycode::S) { dx - step } else { dx };
-
-        let dy = if inp.key_pressed(sdl2::keyboard::Keycode::D) { step } else { 0.0 };
-        let dy = if inp.key_pressed(sdl2::keyboard::Keycode::A) { dy - step } else { dy };
-
-        let dz = if inp.key_pressed(sdl2::keyboard::Keycode::Space) { step } else { 0.0 };
-        let dz = if inp.key_pressed(sdl2::keyboard::Keycode::LCtrl) { dz - step } else { dz };
-
-        self.move_plr(dx, dy, dz)
+    /** Движение по клавишам: WASD, пробел - прыжок (подъем), LShift - бег (быстрый полет), LCtrl - подкрадывание (спуск).
+        В полете клавиши те же, что и до появления ходьбы */
+    pub fn move_by_input(&mut self, inp: &Input, step: f64, game: &Game) {
+        use sdl2::keyboard::Keycode;
+        let axis = |plus: Keycode, minus: Keycode| (inp.key_pressed(plus) as i32 - inp.key_pressed(minus) as i32) as f64;
+        let input = MoveInput {
+            forward: axis(Keycode::W, Keycode::S),
+            right: axis(Keycode::D, Keycode::A),
+            jump: inp.key_pressed(Keycode::Space),
+            sneak: inp.key_pressed(Keycode::LCtrl),
+            sprint: inp.key_pressed(Keycode::LShift),
+        };
+        self.physics_step(input, step, |x, y, z| game.collision_box(x, y, z));
     }
 
     pub fn set_rotation(&mut self, vert: f64, horz: f64) {
@@ -72,7 +121,48 @@ impl Player {
 }
 
 /** Шесть сторон куба, аббревиатуры: Positive/Negative X/Y/Z (по нормали к поверхности стороны) */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum BlockFace { PX, NX, PY, NY, PZ, NZ }
+impl BlockFace {
+    pub const ALL: [BlockFace; 6] = [BlockFace::PX, BlockFace::NX, BlockFace::PY, BlockFace::NY, BlockFace::PZ, BlockFace::NZ];
+
+    /** Единичный сдвиг в сторону нормали */
+    pub fn offset(&self) -> (i32, i32, i32) {
+        match self {
+            BlockFace::PX => ( 1,  0,  0),
+            BlockFace::NX => (-1,  0,  0),
+            BlockFace::PY => ( 0,  1,  0),
+            BlockFace::NY => ( 0, -1,  0),
+            BlockFace::PZ => ( 0,  0,  1),
+            BlockFace::NZ => ( 0,  0, -1),
+        }
+    }
+    /** Противоположная сторона */
+    pub fn opposite(&self) -> BlockFace {
+        match self {
+            BlockFace::PX => BlockFace::NX,
+            BlockFace::NX => BlockFace::PX,
+            BlockFace::PY => BlockFace::NY,
+            BlockFace::NY => BlockFace::PY,
+            BlockFace::PZ => BlockFace::NZ,
+            BlockFace::NZ => BlockFace::PZ,
+        }
+    }
+    /** Сторона по короткому названию: "px", "nx", "py", "ny", "pz", "nz" */
+    pub fn from_name(name: &str) -> Option<BlockFace> {
+        BlockFace::ALL.iter().find(|f| f.name() == name).copied()
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            BlockFace::PX => "px",
+            BlockFace::NX => "nx",
+            BlockFace::PY => "py",
+            BlockFace::NY => "ny",
+            BlockFace::PZ => "pz",
+            BlockFace::NZ => "nz",
+        }
+    }
+}
 
 #[repr(C, packed)]
 #[derive(Clone, Copy, Debug)]
@@ -98,10 +188,10 @@ impl ShapeVertex {
             tex_x, tex_y
         }
     }
-    fn to_vertex(&self, offset: Vec3, material_id: i32, random: i32) -> Vertex {
+    fn to_vertex(&self, offset: Vec3, material_id: i32, random: i32, light: VertexLight) -> Vertex {
         let mut res = self.clone();
         res.pos += offset;
-        Vertex::new(res, material_id, random)
+        Vertex::new(res, material_id, random, light)
     }
 
     fn x(&self) -> f32 { self.pos.x() }
@@ -121,9 +211,12 @@ pub struct BMShape {
     перестает отображаться. Стороны идут в порядке: +x, -x, +y, -y, +z, -z, (обозначает нормаль стороны).
      Если все биты (булеаны) равны нулю, то фигура будет отображаться всегда*/
     overlap_state: DenseBools,
+    /** Стороны, с соседями по которым блок должен соединяться, чтобы фигура отображалась (перекладины заборов и т.д.).
+    Биты в том же порядке, что и в overlap_state. Если все биты равны нулю, то фигура от соседей не зависит */
+    connections: DenseBools,
 }
 impl BMShape {
-    fn new() -> Self { Self { vertices: vec![], indices: vec![], overlap_state: 0u8.into() } }
+    fn new() -> Self { Self { vertices: vec![], indices: vec![], overlap_state: 0u8.into(), connections: 0u8.into() } }
 
     /** Добавить вершину в модель фигуры */
     fn vertex(&mut self, v: ShapeVertex) -> &mut Self {
@@ -170,6 +263,32 @@ impl BMShape {
         self.overlap_state.0 = ovl;
         self
     }
+    /** Отображать фигуру, только если блок соединен с соседом по стороне face */
+    fn connected(&mut self, face: BlockFace) -> &mut Self {
+        self.connections.set(face.into(), true);
+        self
+    }
+    /** Видна ли фигура при закрытости сторон overlap_state и соединениях с соседями connections */
+    fn is_shown(&self, overlap_state: u8, connections: u8) -> bool {
+        let overlapped = self.is_dependent() && overlap_state & self.overlap_state.0 == self.overlap_state.0;
+        !overlapped && connections & self.connections.0 == self.connections.0
+    }
+    /** Если фигура - четырехугольник из двух треугольников, и затенение сильнее меняется вдоль его диагонали,
+        чем поперек, то треугольники разбиваются по другой диагонали (иначе затенение получается несимметричным) */
+    fn flipped_quad(&self, shade: &[f32]) -> Option<Vec<(u32, u32, u32)>> {
+        if self.vertices.len() != 4 || self.indices.len() != 2 { return None; }
+        let (t1, t2) = (self.indices[0], self.indices[1]);
+        let t1 = [t1.0, t1.1, t1.2];
+        let t2 = [t2.0, t2.1, t2.2];
+        //Вершина первого треугольника, не лежащая на общей диагонали
+        let k = t1.iter().position(|i| !t2.contains(i))?;
+        let (a, b, c) = (t1[(k + 2) % 3], t1[k], t1[(k + 1) % 3]);
+        let d = *t2.iter().find(|i| !t1.contains(i))?;
+        let ao = |i: u32| shade[i as usize];
+        if ao(a) + ao(c) >= ao(b) + ao(d) { return None; }
+        Some(vec![(b, c, d), (b, d, a)])
+    }
+
     /** Зависит ли отображение фигуры от закрытости сторон блока*/
     fn is_dependent(&self) -> bool {
         self.overlap_state.0 != 0 //Хотя бы один из битов (булеанов) ненулевой
@@ -233,10 +352,11 @@ pub struct Vertex {
     shape_vert: ShapeVertex,
     material_id: i32,
     random: i32,
+    light: VertexLight, //Свет от блоков, свет неба и затенение вершины соседними блоками (1 - не затенена)
 }
 impl Vertex {
-    fn new(shape_vert: ShapeVertex, material_id: i32, random: i32) -> Self {
-        Vertex{ shape_vert, material_id, random }
+    fn new(shape_vert: ShapeVertex, material_id: i32, random: i32, light: VertexLight) -> Self {
+        Vertex{ shape_vert, material_id, random, light }
     }
 }
 
@@ -249,9 +369,11 @@ pub struct BlockModel {
     solid_faces: DenseBools,
     /** Имя модели */
     name: String,
+    /** Сталкивается ли игрок с блоком (у растений столкновений нет) */
+    collision: bool,
 }
 impl BlockModel {
-    pub fn new(name: String) -> Self { BlockModel{ name, shapes: vec![], solid_faces: DenseBools(0) }  }
+    pub fn new(name: String) -> Self { BlockModel{ name, shapes: vec![], solid_faces: DenseBools(0), collision: true }  }
 
     /** Все стороны блока покрывают всю площадь сторон*/
     pub fn solid(&mut self) -> &mut Self {
@@ -273,33 +395,80 @@ impl BlockModel {
         self.solid_faces = DenseBools(data);
         self
     }
+    /** Сквозь блок можно проходить */
+    pub fn no_collision(&mut self) -> &mut Self {
+        self.collision = false;
+        self
+    }
 
     /** Создать новую группу фигур */
     pub fn new_group(&mut self) -> &mut Self {
         self.shapes.push(vec![]);
         self
     }
-    /** Добавить фигуру в текущую группу */
+    /** Добавить фигуру в текущую группу. Если закрытость сторон, от которой зависит фигура, не задана вручную,
+        она считается по геометрии (BMShape::visible_through). Сплошные стороны модели дополняются по геометрии (covered_faces),
+        проверяются только еще не сплошные стороны, на которых лежит новая фигура */
     pub fn add(&mut self, shape: BMShape) -> &mut Self {
         if self.shapes.len() == 0 { self.new_group(); }
         let id = self.shapes.len() - 1;
         let mut shape = shape;
         shape.calc_geometry();
+        if !shape.is_dependent() {
+            shape.overlap_state = shape.visible_through();
+        }
+        let faces = shape.lying_faces() & !self.solid_faces.0;
         self.shapes[id].push(shape);
+        if faces != 0 {
+            self.solid_faces.0 |= self.covered_faces_among(faces).0;
+        }
         self
     }
     /** Добавить фигуры в текущую группу */
     pub fn add_shapes(&mut self, shapes: Vec<BMShape>) -> &mut Self {
-        if self.shapes.len() == 0 { self.new_group(); }
         for shape in shapes {
-            let id = self.shapes.len() - 1;
-            let mut shape = shape;
-            shape.calc_geometry();
-            self.shapes[id].push(shape);
+            self.add(shape);
         }
         self
     }
 
+    /** Копия модели, повернутая на прямые углы. Стороны в solid_faces и overlap_state поворачиваются вместе с геометрией */
+    pub fn rotated(&self, rotation: BlockRotation) -> BlockModel {
+        let mut model = self.clone();
+        model.name = format!("{}@{}", self.name, rotation.suffix());
+        model.solid_faces = DenseBools(rotation.apply_faces_mask(self.solid_faces.0));
+        for group in model.shapes.iter_mut() {
+            for shape in group.iter_mut() {
+                shape.overlap_state = DenseBools(rotation.apply_faces_mask(shape.overlap_state.0));
+                shape.connections = DenseBools(rotation.apply_faces_mask(shape.connections.0));
+                for v in shape.vertices.iter_mut() {
+                    v.pos = rotation.apply(v.pos);
+                    v.normal = rotation.apply(v.normal);
+                    v.tangent_x = rotation.apply(v.tangent_x);
+                    v.tangent_y = rotation.apply(v.tangent_y);
+                }
+            }
+        }
+        model
+    }
+
+    pub fn name(&self) -> &str { &self.name }
+    pub fn solid_faces(&self) -> DenseBools { self.solid_faces }
+    /** Есть ли фигуры, зависящие от соединений с соседями (см. Game::block_connections) */
+    pub fn has_connections(&self) -> bool { self.shapes.iter().flatten().any(|s| s.connections.0 != 0) }
+
+    /** Треугольники модели при соединениях с соседями connections (координаты вершин относительно центра блока) */
+    pub fn triangles(&self, connections: u8) -> Vec<[Vec3; 3]> {
+        let mut result = vec![];
+        for shape in self.shapes.iter().flatten().filter(|s| s.is_shown(0, connections)) {
+            for i in shape.indices.iter() {
+                let (a, b, c) = (shape.vertices[i.0 as usize].pos, shape.vertices[i.1 as usize].pos, shape.vertices[i.2 as usize].pos);
+                result.push([a, b, c]);
+            }
+        }
+        result
+    }
+
     /** Добавить модель блока в общую (обычно модель чанка) по переданным данным
         vertices        - массив данных вершин модели
         indices         - массив индексов
@@ -308,24 +477,34 @@ impl BlockModel {
         pos             - позиция блока
         atlas_size      - размер атласа*/
     pub fn add_to_model(&self, pos: Vec3, overlap_state: u8, random: i32, vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, textures: &Vec<u32>) {
+        self.add_to_model_lit(pos, (overlap_state, 0), random, (vertices, indices), textures, |_, _| FULL_VERTEX_LIGHT);
+    }
+    /** То же, что add_to_model, но с освещением вершин и соединениями с соседями (overlap_state, connections).
+        light - освещение по положению вершины относительно центра блока и ее нормали */
+    pub fn add_to_model_lit<F>(&self, pos: Vec3, (overlap_state, connections): (u8, u8), random: i32, (vertices, indices): (&mut Vec<Vertex>, &mut Vec<u32>), textures: &[u32], light: F)
+        where F: Fn(Vec3, Vec3) -> VertexLight
+    {
         if textures.len() < self.shapes.len() { panic!("Not enough textures passed to BlockModel") }
 
         for (group_id, group) in self.shapes.iter().enumerate() {
             let material_id = textures[group_id];
 
             for shape in group.iter() {
-                //Если фигура перекрыта другими блоками - пропуск
-                if shape.is_dependent() && ( overlap_state & shape.overlap_state.0 == shape.overlap_state.0 ) {
+                //Если фигура перекрыта другими блоками или не соединена с соседом - пропуск
+                if !shape.is_shown(overlap_state, connections) {
                     continue;
                 }
 
                 let start_index = vertices.len() as u32;
                 //Добавление вершин
-                for v in shape.vertices.iter() {
-                    vertices.push(v.to_vertex(pos, material_id as i32, random) );
+                let lights: Vec<VertexLight> = shape.vertices.iter().map(|v| light(v.pos, v.normal)).collect();
+                for (v, light) in shape.vertices.iter().zip(lights.iter()) {
+                    vertices.push(v.to_vertex(pos, material_id as i32, random, *light) );
                 }
                 //Добавление индексов
-                for i in shape.indices.iter() {
+                let shade: Vec<f32> = lights.iter().map(|l| l[2]).collect();
+                let flipped = shape.flipped_quad(&shade);
+                for i in flipped.as_ref().unwrap_or(&shape.indices).iter() {
                     indices.push(i.0 + start_index);
                     indices.push(i.1 + start_index);
                     indices.push(i.2 + start_index);
@@ -342,11 +521,21 @@ pub struct BlockData {
     pub textures: Vec<u32>,  //Номера текстур, подаваемых в модель
 
     pub name: String,            //Очевидно, название блока
+
+    pub properties: Vec<BlockProperty>,             //Свойства состояния блока (ось, направление и т.д.)
+    pub texture_overrides: Vec<TextureOverride>,    //Текстуры, зависящие от значений свойств
+    pub model_overrides: Vec<ModelOverride>,        //Модели, зависящие от значений свойств
+    pub variants: Vec<BlockVariant>,                //Модель и текстуры для каждого состояния, заполняется в Game::add_block
+
+    pub emission: u8,            //Уровень испускаемого света (0 - не светится, до MAX_LIGHT)
+    pub render_layer: RenderLayer, //Как рисуется блок: непрозрачным, с дырами или полупрозрачным
 }
 
+/** Действие над выгруженным чанком, отложенное до его загрузки. Координаты - мировые координаты блока */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum WaitingAction {
-    //id, x, y, z
-    SetBlock(u8, i32, i32, i32),
+    //id, state, x, y, z
+    SetBlock(BlockId, BlockState, i32, i32, i32),
     //dense_faces, x, y, z
     UpdateFaces(u8, i32, i32, i32)
 }
@@ -359,30 +548,24 @@ pub struct Game {
     blocks:     Vec<BlockData>,
     block_ids:  Vec<(String, usize)>,
 
-    pub chunks:       Vec<Chunk>,         //Чанки в неопределенном порядке
-    chunk_models: Vec<Option<Model>>, //Модели чанков (чанк может быть пуст)
-    chunks_grid:  Vec<Option<usize>>, //Номера чанков по сетке (чанк может быть и не загружен)
-    chunks_start: (i32, i32, i32),    //Начало и конец прямоугольного паралеллепипеда чанков
-    chunks_end:   (i32, i32, i32),
+    chunks:       ChunkMap,                   //Загруженные чанки по их координатам
+    chunk_models: HashMap<ChunkPos, ChunkModel>, //Модели чанков (у чанка модели может и не быть)
+    dirty:        HashSet<ChunkPos>,          //Чанки, модели которых нужно перестроить
+    visibility:   HashMap<ChunkPos, ChunkVisibility>, //Связность сторон чанков, считается вместе с моделью
 
     //Действие может быть применено к выгруженному чанку, тогда оно попадает в ожидание на выполнение
-    waiting_actions: Vec<Option<WaitingAction>>,
+    //Действия хранятся по чанкам, к которым они относятся, в порядке поступления
+    waiting_actions: HashMap<ChunkPos, Vec<WaitingAction>>,
+
+    workers: Option<ChunkWorkers>,          //Фоновые потоки генерации и построения моделей
+    generating: HashSet<ChunkPos>,          //Чанки, которые сейчас генерируются
+    meshing: HashMap<ChunkPos, u64>,        //Чанки, модели которых сейчас строятся, и их версии
 }
 impl Game {
-    pub fn new(res: &Resources) -> Self {
-        let mut textures_required: Vec<String> = vec![
-            "dirt", "grass_side", "grass_top", "stone",
-            "log_side", "log_top", "log_top_cyl", "leaves"
-        ].iter().map(|x| x.to_string()).collect();
-        let blocks_tmp: Vec<(String, Vec<u32>, String)> = vec![
-            ("air".into(),      vec![0u32, 0, 0, 0, 0, 0], "empty".into()),
-            ("dirt".into(),     vec![1u32, 1, 1, 1, 1, 1], "cube".into()),
-            ("grass".into(),    vec![2u32, 2, 2, 2, 3, 1], "cube".into()),
-            ("stone".into(),    vec![4u32, 4, 4, 4, 4, 4], "cube".into()),
-            ("log".into(),      vec![5u32, 5, 5, 5, 6, 6], "cube".into()),
-            ("log_cyl".into(),  vec![5u32, 7, 7],          "cyl_low".into()),
-            ("leaves".into(),   vec![8u32, 8, 8, 8, 8, 8], "cube".into()),
-        ];
+    pub fn new(res: &Resources) -> Result<Self, RegistryError> {
+        let registry = BlockRegistry::load(res, "blocks.json")?;
+        registry.check_textures(res, PathBuf::from("textures"))?;
+        let mut textures_required = registry.texture_names().clone();
 
         let mut atlas = AtlasBuilder::new(15, 15);
         atlas.add_names(&mut textures_required)
@@ -390,6 +573,8 @@ impl Game {
             .load_normals(PathBuf::from("normal_maps"), res)
             .load_lightmaps(PathBuf::from("light_maps"), res);
         let atlas = atlas.build();
+        //Отладочная копия атласа. В тестах не сохраняется, чтобы не менять файлы в assets
+        #[cfg(not(test))]
         println!("Save: {:?}", atlas.image().save(PathBuf::from("assets/tmp_atlas.png")));
         /*for level in 0..5 {
             let img = generate_mipmap(atlas.image(), (15, 15), level);
@@ -403,180 +588,372 @@ impl Game {
             blocks:         vec![],
             block_ids:      vec![],
 
-            chunks:         vec![],
-            chunk_models:   vec![],
-            chunks_grid:    vec![],
-            chunks_start:   (i32::MAX, i32::MAX, i32::MAX),
-            chunks_end:     (i32::MIN, i32::MIN, i32::MIN),
+            chunks:         ChunkMap::new(),
+            chunk_models:   HashMap::new(),
+            dirty:          HashSet::new(),
+            visibility:     HashMap::new(),
+
+            waiting_actions: HashMap::new(),
 
-            waiting_actions: vec![],
+            workers:        None,
+            generating:     HashSet::new(),
+            meshing:        HashMap::new(),
         };
 
         result.add_model(BlockModel::new("empty".into()))
             .add_model(cube_block_model())
-            .add_model(cylinder_block_model());
+            .add_model(cylinder_block_model())
+            .add_model(slab_block_model(false))
+            .add_model(slab_block_model(true))
+            .add_model(stairs_block_model())
+            .add_model(fence_block_model())
+            .add_model(wall_block_model())
+            .add_model(pane_block_model())
+            .add_model(cross_block_model());
+        for model in load_block_models(res, PathBuf::from("models"))? {
+            if result.get_model_id(model.name.clone()).is_ok() {
+                return Err(RegistryError::DuplicateModel(model.name));
+            }
+            result.add_model(model);
+        }
 
-        for b in blocks_tmp {
-            result.add_block(BlockData {
-                name: b.0,
-                model_id: result.get_model_id(b.2).unwrap(),
-                textures: b.1,
-            });
+        let blocks = registry.build_blocks(|model| {
+            let id = result.get_model_id(model.into()).ok()?;
+            Some((id, result.models[id].shapes.len()))
+        })?;
+        for block in blocks {
+            result.add_block(block)?;
         }
 
-        ;result
+        Ok(result)
     }
 
-    pub fn add_chunk(&mut self, chunk: Chunk) {
-        let (cx, cy, cz) = (chunk.x.clone(), chunk.y.clone(), chunk.z.clone());
-
-        let mut changed = false;
-        let mut new_start = self.chunks_start.clone();
-        if cx < new_start.0 {  new_start.0 = cx; changed = true;  }
-        if cy < new_start.1 {  new_start.1 = cy; changed = true;  }
-        if cz < new_start.2 {  new_start.2 = cz; changed = true;  }
+    /** Загрузить чанк. Если на его месте уже был загружен чанк, то он будет заменен */
+    pub fn add_chunk(&mut self, chunk: Chunk) { self.insert_chunk(chunk, false); }
+    /** Загрузить чанк. lit - свет внутри чанка уже рассчитан (Chunk::light_local), например в фоновом потоке,
+        тогда в главном потоке свет только согласуется с соседями */
+    fn insert_chunk(&mut self, chunk: Chunk, lit: bool) {
+        let pos = chunk.pos();
+        self.chunks.insert(chunk);
+        self.chunk_models.remove(&pos);
+        self.meshing.remove(&pos);
+        self.dirty.insert(pos);
+        //У соседей поменялось окружение (затенение углов)
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let neighbour_pos = (pos.0 + dx, pos.1 + dy, pos.2 + dz);
+                    if let Some(neighbour) = self.chunks.get_mut(neighbour_pos) {
+                        neighbour.mark_changed();
+                        self.dirty.insert(neighbour_pos);
+                    }
+                }
+            }
+        }
 
-        let mut new_end = self.chunks_end.clone();
-        if cx < new_end.0 {  new_end.0 = cx; changed = true;  }
-        if cy < new_end.1 {  new_end.1 = cy; changed = true;  }
-        if cz < new_end.2 {  new_end.2 = cz; changed = true;  }
+        self.link_chunk_borders(pos);
+        if lit {
+            self.light_chunk_borders(pos);
+        } else {
+            self.light_chunk(pos);
+        }
+        self.apply_waiting_actions(pos);
+    }
+    /** Выгрузить чанк вместе с его моделью */
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        self.chunk_models.remove(&pos);
+        self.meshing.remove(&pos);
+        self.dirty.remove(&pos);
+        self.visibility.remove(&pos);
+        self.chunks.remove(pos)
+    }
 
-        self.chunks_start = new_start;
-        self.chunks_end = new_end;
-        self.chunks.push(chunk);
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> { self.chunks.get(pos) }
+    /** Чанк для изменения. Его модель будет перестроена, если он изменится */
+    pub fn chunk_mut(&mut self, pos: ChunkPos) -> Option<&mut Chunk> {
+        let chunk = self.chunks.get_mut(pos)?;
+        self.dirty.insert(pos);
+        Some(chunk)
+    }
+    pub fn is_chunk_loaded(&self, pos: ChunkPos) -> bool { self.chunks.contains(pos) }
 
-        if changed {
-            self.reload_grid();
-        } else {
-            let chunk_id = self.chunk_id(cx, cy, cz);
-            self.chunks_grid[chunk_id] = Some(self.chunks.len() - 1);
+    /** Блок и его состояние по мировым координатам. None, если чанк не загружен */
+    pub fn block_at(&self, x: i32, y: i32, z: i32) -> Option<(BlockId, BlockState)> {
+        let chunk = self.chunks.get(chunk_pos_of(x, y, z))?;
+        let (lx, ly, lz) = (to_chunk_mod(x) as i32, to_chunk_mod(y) as i32, to_chunk_mod(z) as i32);
+        Some((chunk.block(lx, ly, lz), chunk.state(lx, ly, lz)))
+    }
+    /** Модель блока с учетом его состояния */
+    pub fn block_model(&self, block: BlockId, state: BlockState) -> &BlockModel {
+        &self.models[self.blocks[block as usize].variant(state).model_id]
+    }
+    /** Стороны, по которым блок соединяется с соседями (биты по BlockFace): соседи того же блока и соседи,
+        закрывающие сторону целиком. 0 для блоков, модель которых от соединений не зависит */
+    pub fn block_connections(&self, x: i32, y: i32, z: i32) -> u8 {
+        let (block, state) = match self.block_at(x, y, z) {
+            Some(b) => b,
+            None => return 0,
+        };
+        if !self.block_model(block, state).has_connections() { return 0; }
+        let chunk = self.chunks.get(chunk_pos_of(x, y, z)).unwrap();
+        let mut connections = chunk.faces(to_chunk_mod(x) as i32, to_chunk_mod(y) as i32, to_chunk_mod(z) as i32).0;
+        for face in BlockFace::ALL {
+            let (dx, dy, dz) = face.offset();
+            if self.block_at(x + dx, y + dy, z + dz).map(|b| b.0) == Some(block) {
+                connections |= 1 << usize::from(face);
+            }
         }
+        connections
     }
-    pub fn reload_grid(&mut self) {
-        if self.chunks_start.0 > self.chunks_end.0 ||
-           self.chunks_start.1 > self.chunks_end.1 ||
-           self.chunks_start.2 > self.chunks_end.2  {
-            self.chunks_grid = vec![];
-            self.chunks_start = (0, 0, 0);
-            self.chunks_end = (-1, -1, -1);
+
+    pub fn set_block(&mut self, block: BlockId, x: i32, y: i32, z: i32) {
+        self.set_block_state(block, 0, x, y, z);
+    }
+
+    pub fn set_block_state(&mut self, block: BlockId, state: BlockState, x: i32, y: i32, z: i32) {
+        let in_chunk_local_pos = (to_chunk_mod(x), to_chunk_mod(y), to_chunk_mod(z));
+
+        //Модель устанавливаемого блока
+        let solid_faces = self.block_solid_faces(block, state);
+        let old = self.block_at(x, y, z);
+
+        match self.chunks.get_mut(chunk_pos_of(x, y, z)) {
+            Some(chunk) => {
+                chunk.set_block_state(block, state, in_chunk_local_pos.0 as i32, in_chunk_local_pos.1 as i32, in_chunk_local_pos.2 as i32, solid_faces);
+                self.mark_dirty(chunk_pos_of(x, y, z));
+            }
+            None => {
+                //Чанк выгружен, откладываем действие
+                self.push_waiting_action(chunk_pos_of(x, y, z), WaitingAction::SetBlock(block, state, x, y, z));
+                return;
+            }
         }
-        println!("{:?} <> {:?}", self.chunks_start, self.chunks_end);
-        let volume = ((self.chunks_end.0 - self.chunks_start.0 + 1) *
-                          (self.chunks_end.1 - self.chunks_start.1 + 1) *
-                          (self.chunks_end.2 - self.chunks_start.2 + 1)) as usize;
+        self.update_faces(solid_faces, x, y, z);
+        if old != Some((block, state)) {
+            if let Some(chunk) = self.chunks.get_mut(chunk_pos_of(x, y, z)) {
+                chunk.mark_edited();
+            }
+            self.mark_halo_neighbours(x, y, z);
+            self.update_light(x, y, z);
+        }
+    }
 
-        if volume > MAX_GRID_SIZE {
-            panic!("Too large chunks grid");
+    /** Отметить закрытость сторон соседей блока на мировых координатах x, y, z, в том числе в соседних чанках.
+        Если соседний чанк не загружен - действие откладывается до его загрузки */
+    pub fn update_faces(&mut self, solid_faces: DenseBools, x: i32, y: i32, z: i32) {
+        let chs = CHUNK_SIZE as i32;
+        let chunk_pos = chunk_pos_of(x, y, z);
+        let local = (to_chunk_mod(x) as i32, to_chunk_mod(y) as i32, to_chunk_mod(z) as i32);
+
+        if let Some(chunk) = self.chunks.get_mut(chunk_pos) {
+            chunk.update_faces(local.0, local.1, local.2, solid_faces);
+            self.mark_dirty(chunk_pos);
         }
 
-        self.chunks_grid = [None; 1].iter().cycle().take(volume).map(|x| *x).collect();
+        for face in BlockFace::ALL {
+            let (dx, dy, dz) = face.offset();
+            //Сосед блока с этой стороны лежит в том же чанке
+            if !is_on_chunk_border(local, (dx, dy, dz)) {
+                continue;
+            }
 
-        for (i, chunk) in self.chunks.iter().enumerate() {
-            if self.is_in_grid(chunk.x, chunk.y, chunk.z) {
-                let chunk_id = self.chunk_id(chunk.x, chunk.y, chunk.z);
-                self.chunks_grid[chunk_id] = Some(i);
+            //Позиция блока в системе координат соседнего чанка (выходит за его пределы на единицу)
+            let neighbour_local = (local.0 - dx * chs, local.1 - dy * chs, local.2 - dz * chs);
+            let neighbour_pos = (chunk_pos.0 + dx, chunk_pos.1 + dy, chunk_pos.2 + dz);
+            match self.chunks.get_mut(neighbour_pos) {
+                Some(chunk) => {
+                    chunk.update_faces(neighbour_local.0, neighbour_local.1, neighbour_local.2, solid_faces);
+                    self.mark_dirty(neighbour_pos);
+                }
+                None => {
+                    self.push_waiting_action(neighbour_pos, WaitingAction::UpdateFaces(solid_faces.0, x, y, z));
+                }
             }
         }
     }
 
-    pub fn get_chunk_id(&mut self, x: i32, y: i32, z: i32) -> Option<usize> {
-        if !self.is_in_grid(x, y, z) {
-            return None;
+    /** Отметить измененными соседние чанки, в окрестность которых (ChunkHalo) попадает блок x, y, z */
+    fn mark_halo_neighbours(&mut self, x: i32, y: i32, z: i32) {
+        let chs = CHUNK_SIZE as i32;
+        let chunk_pos = chunk_pos_of(x, y, z);
+        let local = [to_chunk_mod(x) as i32, to_chunk_mod(y) as i32, to_chunk_mod(z) as i32];
+        //По каждой оси: 0 и, если блок на границе, сторона этой границы
+        let sides = local.map(|l| if l == 0 { &[0, -1][..] } else if l == chs - 1 { &[0, 1][..] } else { &[0][..] });
+        for dx in sides[0] {
+            for dy in sides[1] {
+                for dz in sides[2] {
+                    if (*dx, *dy, *dz) == (0, 0, 0) { continue; }
+                    let neighbour_pos = (chunk_pos.0 + dx, chunk_pos.1 + dy, chunk_pos.2 + dz);
+                    if let Some(chunk) = self.chunks.get_mut(neighbour_pos) {
+                        chunk.mark_changed();
+                        self.dirty.insert(neighbour_pos);
+                    }
+                }
+            }
         }
-        self.chunks_grid[self.chunk_id(x, y, z)]
     }
 
-    pub fn set_block(&mut self, block: u8, x: i32, y: i32, z: i32) {
-        //Чанк выгружен, откладываем действие.
-        if !self.is_in_grid(x, y, z) {
-            self.waiting_actions.push(Some(WaitingAction::SetBlock(block, x, y, z)));
+    /** Синхронизация закрытости сторон на границах только что загруженного чанка и уже загруженных соседей */
+    fn link_chunk_borders(&mut self, pos: ChunkPos) {
+        let chs = CHUNK_SIZE as i32;
+        if !self.chunks.contains(pos) {
             return;
         }
-        let chs = CHUNK_SIZE as i32;
-        let chunk_id = self.chunk_id(x / chs, y / chs, z / chs);
 
-        //Чанк выгружен, откладываем действие
-        if let None = self.chunks_grid[chunk_id] {
-            self.waiting_actions.push(Some(WaitingAction::SetBlock(block, x, y, z)));
-        }
+        for face in BlockFace::ALL {
+            let (dx, dy, dz) = face.offset();
+            let neighbour_pos = (pos.0 + dx, pos.1 + dy, pos.2 + dz);
+            if !self.chunks.contains(neighbour_pos) {
+                continue;
+            }
 
-        let target_chunk_id = &self.chunks_grid[chunk_id].unwrap();
-        let target_chunk = &mut self.chunks[*target_chunk_id];
+            //Пары (блок нового чанка, прилегающий к нему блок соседа) вдоль общей границы
+            let pairs: Vec<((i32, i32, i32), DenseBools, DenseBools)> = {
+                let chunk = self.chunks.get(pos).unwrap();
+                let neighbour = self.chunks.get(neighbour_pos).unwrap();
+                chunk_border(face).map(|(x, y, z)| {
+                    let (nx, ny, nz) = (to_chunk_mod(x + dx) as i32, to_chunk_mod(y + dy) as i32, to_chunk_mod(z + dz) as i32);
+                    let solid = self.block_solid_faces(chunk.block(x, y, z), chunk.state(x, y, z));
+                    let neighbour_solid = self.block_solid_faces(neighbour.block(nx, ny, nz), neighbour.state(nx, ny, nz));
+                    ((x, y, z), solid, neighbour_solid)
+                }).collect()
+            };
+
+            for ((x, y, z), solid_faces, neighbour_solid_faces) in pairs {
+                //Блок нового чанка закрывает сторону соседа
+                self.chunks.get_mut(neighbour_pos).unwrap()
+                    .update_faces(x - dx * chs, y - dy * chs, z - dz * chs, solid_faces);
+
+                //Блок соседа закрывает сторону нового чанка
+                let solid_faces = neighbour_solid_faces;
+                self.chunks.get_mut(pos).unwrap()
+                    .update_faces(x + dx, y + dy, z + dz, solid_faces);
+            }
+            self.mark_dirty(neighbour_pos);
+        }
+    }
 
-        let in_chunk_local_pos = (to_chunk_mod(x), to_chunk_mod(y), to_chunk_mod(z));
+    /** Выполнение (в порядке поступления) отложенных действий над только что загруженным чанком.
+        Выполненные действия удаляются из очереди */
+    fn apply_waiting_actions(&mut self, pos: ChunkPos) {
+        let chs = CHUNK_SIZE as i32;
+        let actions = match self.waiting_actions.remove(&pos) {
+            Some(actions) => actions,
+            None => return,
+        };
 
-        //Модель устанавливаемого блока
-        let block_model = &self.models[self.blocks[block as usize].model_id];
-        let solid_faces = block_model.solid_faces.clone();
-
-        target_chunk.set_block(block, in_chunk_local_pos.0 as i32, in_chunk_local_pos.1 as i32, in_chunk_local_pos.2 as i32, solid_faces);
-        for dx in -1..2 {
-            for dy in -1..2 {
-                for dz in -1..2 {
-                    if let Some(id) = self.get_chunk_id(dx + x / chs, dy + y / chs, dz + z / chs) {
-                        self.chunks[id].update_faces(in_chunk_local_pos.0 as i32 - dx * chs,
-                                                     in_chunk_local_pos.1 as i32 - dy * chs,
-                                                     in_chunk_local_pos.2 as i32 - dz * chs,
-                                                     solid_faces);
+        for action in actions {
+            match action {
+                WaitingAction::SetBlock(block, state, x, y, z) => {
+                    self.set_block_state(block, state, x, y, z);
+                }
+                WaitingAction::UpdateFaces(solid_faces, x, y, z) => {
+                    //Блок лежит в соседнем чанке, вплотную к этому
+                    if let Some(chunk) = self.chunks.get_mut(pos) {
+                        chunk.update_faces(x - pos.0 * chs, y - pos.1 * chs, z - pos.2 * chs, DenseBools(solid_faces));
+                        self.mark_dirty(pos);
                     }
                 }
             }
         }
     }
 
-    pub fn update_faces(&mut self, solid_faces: DenseBools, x: i32, y: i32, z: i32) {
-        let chs = CHUNK_SIZE as i32;
-        let chunk_id = self.chunk_id(x / chs, y / chs, z / chs);
+    /** Запомнить чанк для перестройки модели, если он изменился */
+    fn mark_dirty(&mut self, pos: ChunkPos) {
+        if self.chunks.get(pos).is_some_and(|c| c.is_changed()) {
+            self.dirty.insert(pos);
+        }
+    }
 
-        let target_chunk_id = &self.chunks_grid[chunk_id].unwrap();
-        let in_chunk_local_pos = (to_chunk_mod(x), to_chunk_mod(y), to_chunk_mod(z));
+    fn push_waiting_action(&mut self, pos: ChunkPos, action: WaitingAction) {
+        let actions = self.waiting_actions.entry(pos).or_default();
+        //Закрытость сторон от одного и того же блока нужна только последняя: каждая правка блока на границе
+        //откладывает новую, и без этого очередь росла бы с каждой правкой
+        if let WaitingAction::UpdateFaces(_, x, y, z) = action {
+            actions.retain(|a| !matches!(*a, WaitingAction::UpdateFaces(_, ax, ay, az) if (ax, ay, az) == (x, y, z)));
+        }
+        actions.push(action);
+    }
 
-        let gxs = self.grid_x_size();
-        let gys = self.grid_y_size();
-        let _gzs = self.grid_z_size();
-        //Позиция чанка в сетке
-        let chunk_x =  target_chunk_id % gxs;
-        let _chunk_y = (target_chunk_id / gxs) % gys;
-        let _chunk_z =  target_chunk_id / gxs  / gys;
-
-        if chunk_x > 0 {
-            //Чанк со стороны -х от данного
-            if let Some(id) = self.chunks_grid[target_chunk_id - 1] {
-                self.chunks[id].update_faces((in_chunk_local_pos.0 + CHUNK_SIZE) as i32,
-                                             in_chunk_local_pos.1 as i32, in_chunk_local_pos.2 as i32, solid_faces);
-            } else {
-                self.waiting_actions.push(Some(
-                    WaitingAction::UpdateFaces(solid_faces.0, x, y, z)
-                ));
-            }
+    /** Отложенные действия над выгруженным чанком, в порядке их выполнения */
+    pub fn pending_actions(&self, pos: ChunkPos) -> &[WaitingAction] {
+        match self.waiting_actions.get(&pos) {
+            Some(actions) => &actions[..],
+            None => &[],
         }
+    }
+    /** Чанки, у которых есть отложенные действия */
+    pub fn chunks_with_pending_actions(&self) -> impl Iterator<Item = ChunkPos> + '_ {
+        self.waiting_actions.keys().copied()
+    }
+    /** Общее количество отложенных действий */
+    pub fn pending_actions_count(&self) -> usize {
+        self.waiting_actions.values().map(|a| a.len()).sum()
+    }
 
-        if chunk_x < (gxs - 1) {
-            //Чанк со стороны +х от данного
-            if let Some(id) = self.chunks_grid[target_chunk_id + 1] {
-                self.chunks[id].update_faces((in_chunk_local_pos.0 - CHUNK_SIZE) as i32,
-                                             in_chunk_local_pos.1 as i32, in_chunk_local_pos.2 as i32, solid_faces);
-            } else {
-                self.waiting_actions.push(Some(
-                    WaitingAction::UpdateFaces(solid_faces.0, x, y, z)
-                ));
+    /** Сохранить все загруженные чанки */
+    pub fn save_chunks(&self, save: &WorldSave) -> Result<(), WorldError> {
+        save.save_chunks(self.chunks.iter(), &self.block_names())
+    }
+    /** Загрузить сохраненные чанки. Возвращает позиции чанков, которые нашлись в сохранении */
+    pub fn load_chunks(&mut self, save: &WorldSave, positions: &[ChunkPos]) -> Result<Vec<ChunkPos>, WorldError> {
+        let mut by_region: HashMap<RegionPos, Vec<ChunkPos>> = HashMap::new();
+        for pos in positions {
+            by_region.entry(region_pos_of(*pos)).or_default().push(*pos);
+        }
+
+        let mut loaded = vec![];
+        for (region, positions) in by_region {
+            let file = save.read_region(region)?;
+            for pos in positions {
+                if let Some(chunk) = self.read_chunk(&file, pos)? {
+                    self.add_chunk(chunk);
+                    loaded.push(pos);
+                }
             }
         }
-        todo!();
+        Ok(loaded)
     }
-
-    pub fn is_in_grid(&self, x: i32, y: i32, z: i32) -> bool {
-        if  self.chunks_start.0 <= x &&
-            self.chunks_start.1 <= y &&
-            self.chunks_start.2 <= z &&
-            self.chunks_end.0 >= z &&
-            self.chunks_end.1 >= y &&
-            self.chunks_end.2 >= z
-        { true }
-        else
-        { false }
+    /** Прочитать чанк из региона, номера блоков переводятся в текущие по их названиям */
+    pub fn read_chunk(&self, region: &RegionFile, pos: ChunkPos) -> Result<Option<Chunk>, WorldError> {
+        region.get_chunk(pos,
+            |name| self.get_block_id(name.into()).ok().map(|id| id as BlockId),
+            |block, state| self.block_solid_faces(block, state))
+    }
+    /** Блок в состоянии по умолчанию */
+    pub fn block_ref(&self, name: &str) -> Result<BlockRef, RegistryError> {
+        self.block_ref_with(name, &[])
+    }
+    /** Блок в состоянии с указанными значениями свойств */
+    pub fn block_ref_with(&self, name: &str, values: &[(&str, &str)]) -> Result<BlockRef, RegistryError> {
+        let id = self.get_block_id(name.into()).map_err(|_| RegistryError::UnknownBlock(name.into()))?;
+        let block = &self.blocks[id];
+        for (property, value) in values {
+            let known = block.properties.iter().any(|p| p.name == *property && p.values.iter().any(|v| v == value));
+            if !known {
+                return Err(RegistryError::UnknownState(name.into(), property.to_string(), value.to_string()));
+            }
+        }
+        let state = block.state(values).unwrap();
+        let id = id as BlockId;
+        Ok(BlockRef { id, state, solid_faces: self.block_solid_faces(id, state) })
+    }
+    /** Названия блоков по их номерам */
+    pub fn block_names(&self) -> Vec<String> {
+        self.blocks.iter().map(|b| b.name.clone()).collect()
     }
 
+    /** Стороны блока, закрывающие соседей. Блоки с прозрачностью (не RenderLayer::Opaque) соседей не закрывают */
+    fn block_solid_faces(&self, block: BlockId, state: BlockState) -> DenseBools {
+        if self.blocks[block as usize].render_layer != RenderLayer::Opaque {
+            return DenseBools(0);
+        }
+        self.block_model(block, state).solid_faces
+    }
+    /** Непрозрачный блок: все стороны сплошные. Такие блоки затеняют углы и не пропускают свет */
+    pub fn is_opaque(&self, block: BlockId, state: BlockState) -> bool {
+        is_opaque_block(&self.blocks, &self.models, block, state)
+    }
     pub fn add_model(&mut self, model: BlockModel) -> &mut Self {
         if let Ok(_) = self.get_model_id(model.name.clone()) {
             println!("Model {} already exists", model.name);
@@ -586,14 +963,40 @@ impl Game {
         self.models.push(model);
         self
     }
-    pub fn add_block(&mut self, block: BlockData) -> &mut Self {
+    /** Зарегистрировать блок и создать модели для всех его состояний. Возвращает номер блока */
+    pub fn add_block(&mut self, block: BlockData) -> Result<usize, RegistryError> {
         if let Ok(_) = self.get_block_id(block.name.clone()) {
-            println!("Block {} already exists", block.name);
-            return self;
+            return Err(RegistryError::DuplicateName(block.name));
+        }
+        //Повороты от нескольких свойств неоднозначны (ось и направление можно сложить по-разному), поэтому свойство с поворотом одно
+        let mut rotating = block.properties.iter().filter(|p| p.kind != PropertyKind::Other);
+        if let (Some(first), Some(second)) = (rotating.next(), rotating.next()) {
+            return Err(RegistryError::MultipleRotations(block.name.clone(), first.name.clone(), second.name.clone()));
         }
+        let mut block = block;
+        block.variants = (0..block.states_count())
+            .map(|state| {
+                let (model_id, rotation, textures) = block.resolve_state(state as BlockState);
+                BlockVariant { model_id: self.rotated_model_id(model_id, rotation), textures }
+            })
+            .collect();
+
         self.block_ids.push((block.name.clone(), self.blocks.len()));
         self.blocks.push(block);
-        self
+        Ok(self.blocks.len() - 1)
+    }
+
+    /** Номер повернутой копии модели. Если такой копии еще нет - она будет создана */
+    fn rotated_model_id(&mut self, model_id: usize, rotation: BlockRotation) -> usize {
+        if rotation.is_identity() {
+            return model_id;
+        }
+        let model = self.models[model_id].rotated(rotation);
+        if let Ok(id) = self.get_model_id(model.name.clone()) {
+            return id;
+        }
+        self.add_model(model);
+        self.models.len() - 1
     }
 
     pub fn get_model_id(&self, name: String) -> Result<usize, ()> {
@@ -609,119 +1012,306 @@ impl Game {
         Err(())
     }
 
-    fn chunk_id(&self, x: i32, y: i32, z: i32) -> usize {
-        (x - self.chunks_start.0) as usize +
-        (y - self.chunks_start.1) as usize * self.grid_x_size() +
-        (z - self.chunks_start.2) as usize * self.grid_x_size() * self.grid_y_size()
-    }
-
-    fn grid_x_size(&self) -> usize { (self.chunks_end.0 - self.chunks_start.0) as usize }
-    fn grid_y_size(&self) -> usize { (self.chunks_end.1 - self.chunks_start.1) as usize }
-    fn grid_z_size(&self) -> usize { (self.chunks_end.2 - self.chunks_start.2) as usize }
-
     pub fn models(&self) -> &Vec<BlockModel> { &self.models }
     pub fn blocks(&self) -> &Vec<BlockData> { &self.blocks }
     pub fn atlas(&self) -> &Atlas { &self.atlas }
+    pub fn chunks(&self) -> &ChunkMap { &self.chunks }
+    pub fn chunk_model(&self, pos: ChunkPos) -> Option<&ChunkModel> { self.chunk_models.get(&pos) }
+    pub fn chunk_model_mut(&mut self, pos: ChunkPos) -> Option<&mut ChunkModel> { self.chunk_models.get_mut(&pos) }
+
+    /** Чанки, модели которых нужно (пере)построить: измененные с прошлой перестройки и еще не имеющие модели.
+        Сколько бы раз чанк ни менялся между кадрами, он перестраивается один раз */
+    pub fn chunks_to_rebuild(&self) -> Vec<ChunkPos> {
+        self.dirty.iter().copied().collect()
+    }
+    /** Количество чанков, ждущих перестройки модели */
+    pub fn dirty_chunks_count(&self) -> usize { self.dirty.len() }
+    /** Запустить фоновые потоки генерации и построения моделей чанков */
+    pub fn start_workers(&mut self, threads: usize, generator: Arc<dyn WorldGenerator>, seed: u64) {
+        let context = MeshContext {
+            blocks: self.blocks.clone(),
+            models: self.models.clone(),
+            materials: self.atlas.textures().clone(),
+        };
+        self.workers = Some(ChunkWorkers::new(threads, Arc::new(context), generator, seed));
+    }
+    /** Сгенерировать чанк в фоне. Готовый чанк загрузится в update_chunk_models.
+        Ничего не делает, если чанк уже загружен или генерируется, или фоновые потоки не запущены */
+    pub fn request_chunk(&mut self, pos: ChunkPos) {
+        if self.chunks.contains(pos) || self.generating.contains(&pos) { return; }
+        if let Some(workers) = &self.workers {
+            workers.send(ChunkJob::Generate(pos));
+            self.generating.insert(pos);
+        }
+    }
+    /** Генерируется ли чанк в фоне */
+    pub fn is_chunk_pending(&self, pos: ChunkPos) -> bool { self.generating.contains(&pos) }
+    /** Чанки, которые сейчас генерируются */
+    pub fn pending_chunks(&self) -> Vec<ChunkPos> { self.generating.iter().copied().collect() }
+    pub fn pending_chunks_count(&self) -> usize { self.generating.len() }
+    /** Отменить генерацию чанка: готовый чанк будет отброшен */
+    pub fn cancel_chunk(&mut self, pos: ChunkPos) { self.generating.remove(&pos); }
+
+    /** Загрузить готовые чанки и модели из фоновых потоков и отправить туда измененные чанки.
+        Генерация, свет внутри сгенерированного чанка и построение моделей идут в фоне. В главном потоке остаются
+        согласование света с соседями, копирование чанка и сбор его окрестности для задачи и загрузка моделей на видеокарту.
+        Работа прекращается, когда на нее потрачено больше budget.
+        Без фоновых потоков модели строятся сразу, все. Возвращает позиции чанков с обновленными моделями */
+    pub fn update_chunk_models(&mut self, budget: Duration) -> Vec<ChunkPos> {
+        if self.workers.is_none() {
+            let positions: Vec<ChunkPos> = self.dirty.drain().collect();
+            for pos in positions.iter() {
+                let halo = self.chunk_halo(*pos);
+                let chunk = self.chunks.get_mut(*pos).unwrap();
+                chunk.clear_changed();
+                let mesh = chunk.build_mesh(&self.blocks, &self.models, self.atlas.textures(), &halo);
+                self.visibility.insert(*pos, chunk.visibility(&self.blocks, &self.models));
+                self.chunk_models.insert(*pos, mesh.upload());
+            }
+            return positions;
+        }
+
+        let start = Instant::now();
+        let mut updated = vec![];
+        while start.elapsed() < budget {
+            let result = match self.workers.as_ref().and_then(|w| w.try_recv()) {
+                Some(result) => result,
+                None => break,
+            };
+            match result {
+                ChunkJobResult::Generated(chunk) => {
+                    //Генерация отменена - чанк больше не нужен
+                    if self.generating.remove(&chunk.pos()) && !self.chunks.contains(chunk.pos()) {
+                        self.insert_chunk(*chunk, true);
+                    }
+                }
+                ChunkJobResult::Meshed { pos, mesh, visibility, version } => {
+                    if self.meshing.get(&pos) == Some(&version) {
+                        self.meshing.remove(&pos);
+                    }
+                    //Пока модель строилась, чанк изменился или выгрузился - модель устарела
+                    if self.chunks.get(pos).map(|c| c.version()) == Some(version) {
+                        self.chunk_models.insert(pos, mesh.upload());
+                        self.visibility.insert(pos, visibility);
+                        updated.push(pos);
+                    }
+                }
+            }
+        }
+
+        //Чанки, модели которых уже строятся, ждут результата, даже если снова изменились
+        for pos in self.chunks_to_rebuild() {
+            if start.elapsed() >= budget { break; }
+            if self.meshing.contains_key(&pos) { continue; }
+            self.dirty.remove(&pos);
+            let halo = self.chunk_halo(pos);
+            let chunk = self.chunks.get_mut(pos).unwrap();
+            chunk.clear_changed();
+            let version = chunk.version();
+            let job = ChunkJob::Mesh { chunk: Box::new(chunk.clone()), halo: Box::new(halo), version };
+            self.meshing.insert(pos, version);
+            self.workers.as_ref().unwrap().send(job);
+        }
+        updated
+    }
 }
 
+#[derive(Clone)]
 pub struct Chunk {
-    //ID, закрытость другими блоками
     pub x: i32,
     pub y: i32,
     pub z: i32,
 
     changed: bool,
-    blocks_count: usize,
-
-    data: Vec<(u8, DenseBools)>,
+    version: u64,
+    edited: bool,           //Чанк изменен после генерации или загрузки, его нужно сохранить при выгрузке
+    blocks_count: usize,    //Количество непустых блоков
+
+    blocks: Palette<BlockId>,   //ID блоков
+    states: Palette<BlockState>,//Состояния блоков (ориентация, вариант и т.д.)
+    faces:  Palette<u8>,        //Закрытость сторон блоков другими блоками (DenseBools)
+    light:  Palette<u8>,        //Освещенность: младшие 4 бита - свет от блоков, старшие - от неба (LightChannel)
 }
 impl Chunk {
     pub fn empty(x: i32, y: i32, z: i32) -> Self {
         Self {
             x, y, z,
             changed: false,
+            version: next_chunk_version(),
+            edited: false,
             blocks_count: 0,
-            data: [(0u8,DenseBools(0u8)); 1].iter().cycle().take(CHUNK_VOLUME).map(|x| *x).collect()
+            blocks: Palette::filled(CHUNK_VOLUME, 0),
+            states: Palette::filled(CHUNK_VOLUME, 0),
+            faces:  Palette::filled(CHUNK_VOLUME, 0),
+            light:  Palette::filled(CHUNK_VOLUME, 0),
         }
     }
 
-    pub fn set_block(&mut self, block: u8, x: i32, y: i32, z: i32, block_solidness: DenseBools) {
-        let chs = CHUNK_SIZE as i32;
-        if  x >= 0 && x < chs &&
-            y >= 0 && y < chs &&
-            z >= 0 && z < chs {
-            self.data[Chunk::pos_id(x as usize, y as usize, z as usize)].0 = block;
+    pub fn set_block(&mut self, block: BlockId, x: i32, y: i32, z: i32, block_solidness: DenseBools) {
+        self.set_block_state(block, 0, x, y, z, block_solidness);
+    }
+
+    /** Поставить блок по локальным координатам */
+    pub fn put_block(&mut self, block: BlockRef, x: i32, y: i32, z: i32) {
+        self.set_block_state(block.id, block.state, x, y, z, block.solid_faces);
+    }
+
+    pub fn set_block_state(&mut self, block: BlockId, state: BlockState, x: i32, y: i32, z: i32, block_solidness: DenseBools) {
+        if Chunk::is_inside(x, y, z) {
+            let id = Chunk::pos_id(x as usize, y as usize, z as usize);
+            let old = self.blocks.get(id);
+            if old == 0 && block != 0 { self.blocks_count += 1; }
+            if old != 0 && block == 0 { self.blocks_count -= 1; }
+            if old != block || self.states.get(id) != state { self.mark_changed(); }
+            self.blocks.set(id, block);
+            self.states.set(id, state);
         }
         self.update_faces(x, y, z, block_solidness);
     }
 
-    pub fn update_faces(&mut self, x: i32, y: i32, z: i32, block_solidness: DenseBools) {
-        self.changed = true;
-        let chs = CHUNK_SIZE as i32;
-        if x < chs - 1 && x >= -1 {     //Блоку на x + 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize + 1, y as usize, z as usize)].1
-                .set(BlockFace::NX.into(), block_solidness.get(BlockFace::PX.into()) );
-        }
-        if x > 0 && x <= chs {     //Блоку на x - 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize - 1, y as usize, z as usize)].1
-                .set(BlockFace::PX.into(), block_solidness.get(BlockFace::NX.into()));
-        }
-        if y < chs - 1 && y >= -1 {     //Блоку на y + 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize, y as usize + 1, z as usize)].1
-                .set(BlockFace::NY.into(), block_solidness.get(BlockFace::PY.into()));
-        }
-        if y > 0 && y <= chs {     //Блоку на y - 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize, y as usize - 1, z as usize)].1
-                .set(BlockFace::PY.into(), block_solidness.get(BlockFace::NY.into()));
-        }
-        if z < chs - 1 && z >= -1 {     //Блоку на z + 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize, y as usize, z as usize + 1)].1
-                .set(BlockFace::NZ.into(), block_solidness.get(BlockFace::PZ.into()));
+    /** Возвращает номер блока по локальным координатам */
+    pub fn block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        self.blocks.get(Chunk::pos_id(x as usize, y as usize, z as usize))
+    }
+    /** Возвращает состояние блока по локальным координатам */
+    pub fn state(&self, x: i32, y: i32, z: i32) -> BlockState {
+        self.states.get(Chunk::pos_id(x as usize, y as usize, z as usize))
+    }
+    /** Возвращает закрытость сторон блока по локальным координатам */
+    pub fn faces(&self, x: i32, y: i32, z: i32) -> DenseBools {
+        DenseBools(self.faces.get(Chunk::pos_id(x as usize, y as usize, z as usize)))
+    }
+    /** Возвращает освещенность блока по локальным координатам (оба уровня, см. LightChannel) */
+    pub fn light(&self, x: i32, y: i32, z: i32) -> u8 {
+        self.light.get(Chunk::pos_id(x as usize, y as usize, z as usize))
+    }
+    pub fn set_light(&mut self, x: i32, y: i32, z: i32, light: u8) {
+        let id = Chunk::pos_id(x as usize, y as usize, z as usize);
+        if self.light.get(id) != light {
+            self.light.set(id, light);
+            self.mark_changed();
         }
-        if z > 0 && z <= chs {     //Блоку на z - 1 нужно отметить закрытость стороны
-            self.data[Chunk::pos_id(x as usize, y as usize, z as usize - 1)].1
-                .set(BlockFace::PZ.into(), block_solidness.get(BlockFace::NZ.into()));
+    }
+    /** Погасить весь свет чанка. Свет не сохраняется и пересчитывается при загрузке */
+    pub fn clear_light(&mut self) {
+        self.light.fill(0);
+        self.mark_changed();
+    }
+    /** Изменился ли чанк (блоки, закрытость сторон или освещенность) с момента построения модели */
+    pub fn is_changed(&self) -> bool { self.changed }
+    pub fn clear_changed(&mut self) { self.changed = false; }
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+        self.version = next_chunk_version();
+    }
+    /** Номер версии, меняется при каждом изменении и не повторяется даже у разных чанков.
+        По нему отбрасываются устаревшие модели из фоновых потоков */
+    pub fn version(&self) -> u64 { self.version }
+    pub fn is_edited(&self) -> bool { self.edited }
+    pub fn mark_edited(&mut self) { self.edited = true; }
+    /** Количество непустых блоков */
+    pub fn blocks_count(&self) -> usize { self.blocks_count }
+    /** Примерный объем памяти, занимаемый данными блоков, в байтах */
+    pub fn memory_usage(&self) -> usize { self.blocks.memory_usage() + self.states.memory_usage() + self.faces.memory_usage() + self.light.memory_usage() }
+
+    /** Отметить закрытость сторон соседей блока на x, y, z. Сам блок может лежать за пределами чанка
+        (на единицу в любую сторону) - тогда обновятся только соседи, лежащие внутри чанка */
+    pub fn update_faces(&mut self, x: i32, y: i32, z: i32, block_solidness: DenseBools) {
+        for face in BlockFace::ALL {
+            let (dx, dy, dz) = face.offset();
+            let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+            if !Chunk::is_inside(nx, ny, nz) {
+                continue;
+            }
+            //Соседу со стороны face нужно отметить закрытость противоположной стороны
+            let id = Chunk::pos_id(nx as usize, ny as usize, nz as usize);
+            let mut faces = DenseBools(self.faces.get(id));
+            faces.set(face.opposite().into(), block_solidness.get(face.into()));
+            if faces.0 != self.faces.get(id) {
+                self.faces.set(id, faces.0);
+                self.mark_changed();
+            }
         }
     }
 
-    pub fn build_model(&self, blocks_data: &Vec<BlockData>, models_data: &Vec<BlockModel>) -> rgl::Model {
-        let mut vertices: Vec<Vertex> = vec![];
-        let mut indices: Vec<u32> = vec![];
+    pub fn is_inside(x: i32, y: i32, z: i32) -> bool {
+        let chs = CHUNK_SIZE as i32;
+        x >= 0 && x < chs &&
+        y >= 0 && y < chs &&
+        z >= 0 && z < chs
+    }
+
+    pub fn pos(&self) -> ChunkPos { (self.x, self.y, self.z) }
+
+    /** Построить модель чанка без обращения к OpenGL (загружается отдельно, ChunkMesh::upload).
+        halo - непрозрачность и освещенность блоков вокруг чанка, нужна для освещения вершин,
+        materials - данные текстур атласа, по ним видно, какие стороны можно объединять.
+        Стороны блоков-кубов объединяются жадным алгоритмом (greedy.rs), остальные модели добавляются поблочно */
+    pub fn build_mesh(&self, blocks_data: &[BlockData], models_data: &[BlockModel], materials: &[TexData], halo: &ChunkHalo) -> ChunkMeshes {
+        let mut meshes = ChunkMeshes::new();
+        if self.blocks_count == 0 {
+            return meshes;
+        }
+        let cube_id = models_data.iter().position(|m| m.name == "cube");
 
         for x in 0..CHUNK_SIZE {
             for y in 0..CHUNK_SIZE {
                 for z in 0..CHUNK_SIZE {
                     let id = Chunk::pos_id(x, y, z);
-                    if self.data[id].0 == 0 {
+                    let block = self.blocks.get(id) as usize;
+                    if block == 0 {
+                        continue;
+                    }
+                    let variant = blocks_data[block].variant(self.states.get(id));
+                    let layer = blocks_data[block].render_layer;
+                    if Some(variant.model_id) == cube_id && layer == RenderLayer::Opaque {
                         continue;
                     }
-                    let random: i32 = {
-                        use std::num::Wrapping;
-                        let x = Wrapping(self.x * (CHUNK_SIZE as i32) + (x as i32));
-                        let y = Wrapping(self.y * (CHUNK_SIZE as i32) + (y as i32));
-                        let z = Wrapping(self.z * (CHUNK_SIZE as i32) + (z as i32));
-                        #[allow(overflowing_literals)]
-                        {
-                            let random = x*y*z + x*y + x*x + y*z + x + y + z;
-                            let random = (random ^ Wrapping(0xF7B2132A)) * Wrapping(0xBB12A45F);
-                            let random = random ^ Wrapping((random.0 as f32).sqrt() as i32) ^ (random * random);
-                            random ^ Wrapping((random.0 as f32).sqrt() as i32) ^ (random * random)
+                    let (bx, by, bz) = (x as i32, y as i32, z as i32);
+                    let model = &models_data[variant.model_id];
+                    //Соседи того же блока (биты по сторонам)
+                    let mut same = 0u8;
+                    if layer == RenderLayer::Translucent || model.has_connections() {
+                        for face in BlockFace::ALL {
+                            let (dx, dy, dz) = face.offset();
+                            if halo.block(bx + dx, by + dy, bz + dz) == block as BlockId {
+                                same |= 1 << usize::from(face);
+                            }
                         }
-                    }.0;
-                    models_data[blocks_data[self.data[id].0 as usize].model_id].add_to_model(
+                    }
+                    //Между одинаковыми полупрозрачными блоками стороны не рисуются
+                    let overlap_state = if layer == RenderLayer::Translucent { self.faces.get(id) | same } else { self.faces.get(id) };
+                    let connections = if model.has_connections() { self.faces.get(id) | same } else { 0 };
+                    let mesh = meshes.layer_mut(layer);
+                    model.add_to_model_lit(
                         mat::Vec3::new(x as f32, y as f32, z as f32),
-                        self.data[id].1.0, random,
-                        &mut vertices, &mut indices,
-                        &blocks_data[self.data[id].0 as usize].textures,
+                        (overlap_state, connections), self.block_random(bx, by, bz),
+                        (&mut mesh.vertices, &mut mesh.indices),
+                        &variant.textures,
+                        |pos, normal| halo.vertex_light(bx, by, bz, pos, normal),
                     );
                 }
             }
         }
+        if let Some(cube_id) = cube_id {
+            self.add_greedy_cubes(blocks_data, &models_data[cube_id], cube_id, materials, halo, &mut meshes.opaque);
+        }
+        meshes
+    }
 
-        use AttribType::*;
-        //Position, Normal, Tangent X, Tangent Y, Tex Coordinates, Texture_ID
-        let attributes: Vec<AttribType> = vec![Vec3, Vec3, Vec3, Vec3, Vec2, Int, Int];
-        texture_model(&vertices, &indices, &attributes)
+    /** Случайное число блока по локальным координатам, от него зависит выбор варианта текстуры */
+    fn block_random(&self, x: i32, y: i32, z: i32) -> i32 {
+        use std::num::Wrapping;
+        let x = Wrapping(self.x * (CHUNK_SIZE as i32) + x);
+        let y = Wrapping(self.y * (CHUNK_SIZE as i32) + y);
+        let z = Wrapping(self.z * (CHUNK_SIZE as i32) + z);
+        #[allow(overflowing_literals)]
+        {
+            let random = x*y*z + x*y + x*x + y*z + x + y + z;
+            let random = (random ^ Wrapping(0xF7B2132A)) * Wrapping(0xBB12A45F);
+            let random = random ^ Wrapping((random.0 as f32).sqrt() as i32) ^ (random * random);
+            (random ^ Wrapping((random.0 as f32).sqrt() as i32) ^ (random * random)).0
+        }
     }
 
     pub fn pos_id(x: usize, y: usize, z: usize) -> usize {
@@ -730,8 +1320,106 @@ impl Chunk {
 
 }
 
+/** Новый номер версии чанка, общий счетчик для всех чанков */
+fn next_chunk_version() -> u64 {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
 fn to_chunk_mod(i: i32) -> usize {
     let chs = CHUNK_SIZE as i32;
     (((i % chs) + chs) % chs) as usize
 }
 
+/** Лежит ли сосед блока (по локальным координатам) в направлении dir за пределами чанка */
+fn is_on_chunk_border(local: (i32, i32, i32), dir: (i32, i32, i32)) -> bool {
+    let chs = CHUNK_SIZE as i32;
+    let n = (local.0 + dir.0, local.1 + dir.1, local.2 + dir.2);
+    n.0 < 0 || n.0 >= chs || n.1 < 0 || n.1 >= chs || n.2 < 0 || n.2 >= chs
+}
+
+/** Локальные координаты всех блоков чанка, прилегающих к его стороне face */
+fn chunk_border(face: BlockFace) -> impl Iterator<Item = (i32, i32, i32)> {
+    let chs = CHUNK_SIZE as i32;
+    (0..chs).flat_map(move |a| (0..chs).map(move |b| {
+        match face {
+            BlockFace::PX => (chs - 1, a, b),
+            BlockFace::NX => (0, a, b),
+            BlockFace::PY => (a, chs - 1, b),
+            BlockFace::NY => (a, 0, b),
+            BlockFace::PZ => (a, b, chs - 1),
+            BlockFace::NZ => (a, b, 0),
+        }
+    }))
+}
+
+
+/** Игра с блоками и моделями из assets, без окна и OpenGL (для тестов) */
+#[cfg(test)]
+fn test_game() -> Game {
+    let res = Resources::from_relative(std::path::Path::new("../assets")).unwrap();
+    Game::new(&res).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_actions_replay_in_order() {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        //Правки в незагруженном чанке (1, 0, 0) откладываются
+        game.set_block(3, 40, 5, 5);
+        game.set_block(1, 40, 5, 5);
+        game.set_block(3, 41, 5, 5);
+        game.set_block(0, 41, 5, 5);
+        assert_eq!(game.pending_actions((1, 0, 0)), &[
+            WaitingAction::SetBlock(3, 0, 40, 5, 5),
+            WaitingAction::SetBlock(1, 0, 40, 5, 5),
+            WaitingAction::SetBlock(3, 0, 41, 5, 5),
+            WaitingAction::SetBlock(0, 0, 41, 5, 5),
+        ]);
+        assert_eq!(game.pending_actions_count(), 4);
+        assert_eq!(game.chunks_with_pending_actions().collect::<Vec<_>>(), vec![(1, 0, 0)]);
+        assert!(game.pending_actions((0, 0, 0)).is_empty());
+
+        //При загрузке правки выполняются по порядку, последняя правка блока остается
+        game.add_chunk(Chunk::empty(1, 0, 0));
+        assert_eq!(game.block_at(40, 5, 5), Some((1, 0)));
+        assert_eq!(game.block_at(41, 5, 5), Some((0, 0)));
+        assert_eq!(game.pending_actions_count(), 0);
+        assert!(game.pending_actions((1, 0, 0)).is_empty());
+        assert_eq!(game.chunks_with_pending_actions().count(), 0);
+    }
+
+    #[test]
+    fn border_edits_keep_one_faces_update() {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        //Блок на границе с незагруженным чанком (1, 0, 0) правится несколько раз
+        for block in [3, 0, 1, 3] {
+            game.set_block(block, 31, 5, 5);
+        }
+        game.set_block(3, 31, 6, 5);
+        let stone = game.block_solid_faces(3, 0).0;
+        assert_eq!(game.pending_actions((1, 0, 0)), &[
+            WaitingAction::UpdateFaces(stone, 31, 5, 5),
+            WaitingAction::UpdateFaces(stone, 31, 6, 5),
+        ]);
+
+        game.set_block(0, 31, 6, 5);
+        assert_eq!(game.pending_actions((1, 0, 0)), &[
+            WaitingAction::UpdateFaces(stone, 31, 5, 5),
+            WaitingAction::UpdateFaces(0, 31, 6, 5),
+        ]);
+
+        //Сторона соседа закрыта только камнем, который остался
+        game.add_chunk(Chunk::empty(1, 0, 0));
+        let chunk = game.chunk((1, 0, 0)).unwrap();
+        assert!(chunk.faces(0, 5, 5).get(BlockFace::NX.into()));
+        assert!(!chunk.faces(0, 6, 5).get(BlockFace::NX.into()));
+        assert_eq!(game.pending_actions_count(), 0);
+    }
+}
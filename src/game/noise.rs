@@ -0,0 +1,131 @@
+/** Градиентный шум Перлина с таблицей перестановок, построенной по сиду.
+    Одинаковый сид всегда дает одинаковый шум */
+#[derive(Clone)]
+pub struct Noise {
+    perm: [u8; 512],
+}
+impl Noise {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = [0; 256];
+        for (i, v) in table.iter_mut().enumerate() { *v = i as u8; }
+        //Перемешивание Фишера-Йетса
+        let mut rng = SplitMix64(seed);
+        for i in (1..256).rev() {
+            let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+            table.swap(i, j);
+        }
+        let mut perm = [0u8; 512];
+        for i in 0..512 { perm[i] = table[i & 255]; }
+        Self { perm }
+    }
+
+    /** Шум в точке, примерно в пределах [-1; 1] */
+    pub fn get2(&self, x: f64, y: f64) -> f64 {
+        let (xi, yi) = (x.floor() as i64 & 255, y.floor() as i64 & 255);
+        let (xf, yf) = (x - x.floor(), y - y.floor());
+        let (u, v) = (fade(xf), fade(yf));
+        let p = &self.perm;
+        let (xi, yi) = (xi as usize, yi as usize);
+
+        let aa = p[p[xi] as usize + yi] as usize;
+        let ab = p[p[xi] as usize + yi + 1] as usize;
+        let ba = p[p[xi + 1] as usize + yi] as usize;
+        let bb = p[p[xi + 1] as usize + yi + 1] as usize;
+
+        let x1 = lerp(grad2(aa, xf, yf), grad2(ba, xf - 1.0, yf), u);
+        let x2 = lerp(grad2(ab, xf, yf - 1.0), grad2(bb, xf - 1.0, yf - 1.0), u);
+        lerp(x1, x2, v)
+    }
+
+    /** Шум в точке, примерно в пределах [-1; 1] */
+    pub fn get3(&self, x: f64, y: f64, z: f64) -> f64 {
+        let (xi, yi, zi) = ((x.floor() as i64 & 255) as usize, (y.floor() as i64 & 255) as usize, (z.floor() as i64 & 255) as usize);
+        let (xf, yf, zf) = (x - x.floor(), y - y.floor(), z - z.floor());
+        let (u, v, w) = (fade(xf), fade(yf), fade(zf));
+        let p = &self.perm;
+
+        let a = p[xi] as usize + yi;
+        let aa = p[a] as usize + zi;
+        let ab = p[a + 1] as usize + zi;
+        let b = p[xi + 1] as usize + yi;
+        let ba = p[b] as usize + zi;
+        let bb = p[b + 1] as usize + zi;
+
+        lerp(
+            lerp(
+                lerp(grad3(p[aa], xf, yf, zf), grad3(p[ba], xf - 1.0, yf, zf), u),
+                lerp(grad3(p[ab], xf, yf - 1.0, zf), grad3(p[bb], xf - 1.0, yf - 1.0, zf), u),
+                v),
+            lerp(
+                lerp(grad3(p[aa + 1], xf, yf, zf - 1.0), grad3(p[ba + 1], xf - 1.0, yf, zf - 1.0), u),
+                lerp(grad3(p[ab + 1], xf, yf - 1.0, zf - 1.0), grad3(p[bb + 1], xf - 1.0, yf - 1.0, zf - 1.0), u),
+                v),
+            w)
+    }
+
+    /** Фрактальный шум (сумма октав), нормирован примерно в [-1; 1] */
+    pub fn fbm2(&self, x: f64, y: f64, octaves: u32) -> f64 {
+        let (mut sum, mut amp, mut freq, mut norm) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..octaves {
+            sum += self.get2(x * freq, y * freq) * amp;
+            norm += amp;
+            amp *= 0.5;
+            freq *= 2.0;
+        }
+        sum / norm
+    }
+    /** Фрактальный шум (сумма октав), нормирован примерно в [-1; 1] */
+    pub fn fbm3(&self, x: f64, y: f64, z: f64, octaves: u32) -> f64 {
+        let (mut sum, mut amp, mut freq, mut norm) = (0.0, 1.0, 1.0, 0.0);
+        for _ in 0..octaves {
+            sum += self.get3(x * freq, y * freq, z * freq) * amp;
+            norm += amp;
+            amp *= 0.5;
+            freq *= 2.0;
+        }
+        sum / norm
+    }
+}
+
+/** Простой генератор псевдослучайных чисел, используется для построения шума и случайных решений генератора мира */
+pub struct SplitMix64(pub u64);
+impl SplitMix64 {
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+    /** Число в [0; 1) */
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/** Хеш координат и сида. Одни и те же аргументы всегда дают одно и то же значение */
+pub fn hash_coords(seed: u64, x: i32, y: i32, z: i32) -> u64 {
+    let mut rng = SplitMix64(seed
+        ^ (x as u32 as u64).wrapping_mul(0x9E3779B1)
+        ^ ((y as u32 as u64).wrapping_mul(0x85EBCA77) << 21)
+        ^ ((z as u32 as u64).wrapping_mul(0xC2B2AE3D) << 42));
+    rng.next_u64()
+}
+
+fn fade(t: f64) -> f64 { t * t * t * (t * (t * 6.0 - 15.0) + 10.0) }
+fn lerp(a: f64, b: f64, t: f64) -> f64 { a + (b - a) * t }
+
+fn grad2(hash: usize, x: f64, y: f64) -> f64 {
+    match hash & 7 {
+        0 =>  x + y, 1 => -x + y, 2 =>  x - y, 3 => -x - y,
+        4 =>  x,     5 => -x,     6 =>  y,     _ => -y,
+    }
+}
+fn grad3(hash: u8, x: f64, y: f64, z: f64) -> f64 {
+    match hash & 15 {
+        0 =>  x + y,  1 => -x + y,  2 =>  x - y,  3 => -x - y,
+        4 =>  x + z,  5 => -x + z,  6 =>  x - z,  7 => -x - z,
+        8 =>  y + z,  9 => -y + z, 10 =>  y - z, 11 => -y - z,
+        12 => x + y, 13 => -y + z, 14 => -x + y, _ => -y - z,
+    }
+}
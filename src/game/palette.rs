@@ -0,0 +1,214 @@
+/** Палитровое хранилище фиксированного количества значений (как секции чанков в Minecraft).
+    Каждое значение хранится как номер в палитре, упакованный в минимально нужное число бит.
+    Если все значения одинаковы, то хранилище сворачивается в одну константу */
+#[derive(Clone, Debug)]
+pub struct Palette<T: Copy + Eq> {
+    len: usize,
+    storage: Storage<T>,
+}
+
+#[derive(Clone, Debug)]
+enum Storage<T: Copy + Eq> {
+    /** Все значения одинаковы */
+    Single(T),
+    Packed {
+        /** Значения, на которые ссылаются номера */
+        palette: Vec<T>,
+        /** Сколько раз используется каждый элемент палитры. Неиспользуемые элементы переиспользуются */
+        counts: Vec<u32>,
+        /** Бит на один номер */
+        bits: u32,
+        /** Упакованные номера. Номер никогда не пересекает границу u64 */
+        data: Vec<u64>,
+    },
+}
+
+impl<T: Copy + Eq> Palette<T> {
+    /** Хранилище из len одинаковых значений */
+    pub fn filled(len: usize, value: T) -> Self {
+        Self { len, storage: Storage::Single(value) }
+    }
+
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    pub fn get(&self, i: usize) -> T {
+        match &self.storage {
+            Storage::Single(value) => *value,
+            Storage::Packed { palette, bits, data, .. } => palette[read_packed(data, *bits, i) as usize],
+        }
+    }
+
+    pub fn set(&mut self, i: usize, value: T) {
+        if let Storage::Single(current) = self.storage {
+            if current == value { return; }
+            //Разворачиваем константу в палитру из двух значений
+            self.storage = Storage::Packed {
+                palette: vec![current, value],
+                counts: vec![self.len as u32, 0],
+                bits: 1,
+                data: vec![0u64; words_count(self.len, 1)],
+            };
+        }
+
+        let len = self.len;
+        let collapse = if let Storage::Packed { palette, counts, bits, data } = &mut self.storage {
+            let old_id = read_packed(data, *bits, i) as usize;
+            if palette[old_id] == value { return; }
+
+            let new_id = match palette.iter().position(|v| *v == value) {
+                Some(id) => id,
+                None => match counts.iter().position(|c| *c == 0) {
+                    //Свободный элемент палитры
+                    Some(id) => { palette[id] = value; id }
+                    None => {
+                        palette.push(value);
+                        counts.push(0);
+                        if palette.len() > (1usize << *bits) {
+                            let new_bits = *bits + 1;
+                            *data = repack(data, *bits, new_bits, len);
+                            *bits = new_bits;
+                        }
+                        palette.len() - 1
+                    }
+                }
+            };
+
+            counts[old_id] -= 1;
+            counts[new_id] += 1;
+            write_packed(data, *bits, i, new_id as u64);
+            counts[new_id] as usize == len
+        } else { false };
+
+        if collapse {
+            self.storage = Storage::Single(value);
+        }
+    }
+
+    /** Заполнить всё хранилище одним значением */
+    pub fn fill(&mut self, value: T) {
+        self.storage = Storage::Single(value);
+    }
+
+    /** Значение, если все значения одинаковы */
+    pub fn single(&self) -> Option<T> {
+        match &self.storage {
+            Storage::Single(value) => Some(*value),
+            Storage::Packed { .. } => None,
+        }
+    }
+
+    /** Используемые значения палитры (без повторов) */
+    pub fn values(&self) -> Vec<T> {
+        match &self.storage {
+            Storage::Single(value) => vec![*value],
+            Storage::Packed { palette, counts, .. } => palette.iter().zip(counts.iter())
+                .filter(|(_, c)| **c > 0)
+                .map(|(v, _)| *v)
+                .collect(),
+        }
+    }
+
+    /** Бит на одно значение (0 для константы) */
+    pub fn bits(&self) -> u32 {
+        match &self.storage {
+            Storage::Single(_) => 0,
+            Storage::Packed { bits, .. } => *bits,
+        }
+    }
+
+    /** Примерный объем занимаемой памяти в байтах */
+    pub fn memory_usage(&self) -> usize {
+        std::mem::size_of::<Self>() + match &self.storage {
+            Storage::Single(_) => 0,
+            Storage::Packed { palette, counts, data, .. } =>
+                palette.len() * std::mem::size_of::<T>() + counts.len() * 4 + data.len() * 8,
+        }
+    }
+}
+
+fn words_count(len: usize, bits: u32) -> usize {
+    let per_word = (64 / bits) as usize;
+    len.div_ceil(per_word)
+}
+
+fn read_packed(data: &[u64], bits: u32, i: usize) -> u64 {
+    let per_word = (64 / bits) as usize;
+    let shift = (i % per_word) as u32 * bits;
+    (data[i / per_word] >> shift) & ((1u64 << bits) - 1)
+}
+
+fn write_packed(data: &mut [u64], bits: u32, i: usize, value: u64) {
+    let per_word = (64 / bits) as usize;
+    let shift = (i % per_word) as u32 * bits;
+    let mask = ((1u64 << bits) - 1) << shift;
+    let word = &mut data[i / per_word];
+    *word = (*word & !mask) | (value << shift);
+}
+
+fn repack(data: &[u64], old_bits: u32, new_bits: u32, len: usize) -> Vec<u64> {
+    let mut res = vec![0u64; words_count(len, new_bits)];
+    for i in 0..len {
+        write_packed(&mut res, new_bits, i, read_packed(data, old_bits, i));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEN: usize = 32 * 32 * 32;
+
+    /** Значение i-й клетки, в котором используется values разных значений */
+    fn pattern(i: usize, values: u32) -> u32 { (i as u32 * 7 + 3) % values }
+
+    #[test]
+    fn grows_and_keeps_values() {
+        let mut palette = Palette::filled(LEN, 0u32);
+        assert_eq!(palette.bits(), 0);
+        //Число бит растет вместе с палитрой, уже записанные значения не меняются
+        for (values, bits) in [(2, 1), (3, 2), (4, 2), (5, 3), (16, 4), (17, 5), (256, 8), (257, 9)] {
+            for i in 0..LEN { palette.set(i, pattern(i, values)); }
+            assert_eq!(palette.bits(), bits, "{} значений", values);
+            assert!((0..LEN).all(|i| palette.get(i) == pattern(i, values)), "{} значений", values);
+        }
+        assert_eq!(palette.len(), LEN);
+    }
+
+    #[test]
+    fn reuses_freed_slots() {
+        let mut palette = Palette::filled(LEN, 0u32);
+        for i in 0..4 { palette.set(i, i as u32); }
+        assert_eq!(palette.bits(), 2);
+        //Значение 3 больше не используется, его место в палитре занимает 4, и бит не прибавляется
+        palette.set(3, 0);
+        palette.set(10, 4);
+        assert_eq!(palette.bits(), 2);
+        let mut values = palette.values();
+        values.sort();
+        assert_eq!(values, vec![0, 1, 2, 4]);
+        assert_eq!((palette.get(3), palette.get(10)), (0, 4));
+    }
+
+    #[test]
+    fn collapses_to_single() {
+        let mut palette = Palette::filled(LEN, 0u32);
+        for i in 0..LEN { palette.set(i, pattern(i, 20)); }
+        let packed = palette.memory_usage();
+        assert!(packed > LEN * 5 / 8);
+
+        palette.fill(7);
+        assert_eq!(palette.single(), Some(7));
+        assert_eq!((palette.bits(), palette.values()), (0, vec![7]));
+        assert!((0..LEN).all(|i| palette.get(i) == 7));
+        assert!(palette.memory_usage() < packed / 100);
+
+        //Хранилище сворачивается и когда последнее отличающееся значение перезаписано
+        palette.set(5, 1);
+        assert_eq!(palette.single(), None);
+        palette.set(5, 7);
+        assert_eq!(palette.single(), Some(7));
+        assert_eq!(palette.memory_usage(), Palette::filled(LEN, 7u32).memory_usage());
+    }
+}
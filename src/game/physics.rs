@@ -0,0 +1,360 @@
+use crate::game::{BlockModel, Game, Player};
+
+/** Размеры игрока в блоках. Координаты игрока - положение глаз */
+pub const PLAYER_WIDTH: f64 = 0.6;
+pub const PLAYER_HEIGHT: f64 = 1.8;
+pub const PLAYER_EYE_HEIGHT: f64 = 1.62;
+/** Наибольшая высота уступа, на который игрок заходит без прыжка */
+pub const STEP_HEIGHT: f64 = 0.6;
+
+const GRAVITY: f64 = 28.0;
+const JUMP_SPEED: f64 = 8.4;
+const MAX_FALL_SPEED: f64 = 60.0;
+const WALK_SPEED: f64 = 4.3;
+const SPRINT_SPEED: f64 = 5.6;
+const SNEAK_SPEED: f64 = 1.3;
+/** Как быстро скорость подстраивается под желаемую (трение) на земле и в воздухе */
+const GROUND_ACCELERATION: f64 = 16.0;
+const AIR_ACCELERATION: f64 = 2.5;
+const FLY_SPEED: f64 = 4.0;
+const FLY_FAST_SPEED: f64 = 80.0;
+/** Наибольший шаг симуляции, более длинные кадры делятся на несколько шагов */
+const MAX_STEP: f64 = 0.02;
+
+/** Прямоугольный параллелепипед, выровненный по осям */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f64; 3],
+    pub max: [f64; 3],
+}
+impl Aabb {
+    pub fn new(min: [f64; 3], max: [f64; 3]) -> Self { Self { min, max } }
+
+    pub fn offset(&self, dx: f64, dy: f64, dz: f64) -> Self {
+        Self {
+            min: [self.min[0] + dx, self.min[1] + dy, self.min[2] + dz],
+            max: [self.max[0] + dx, self.max[1] + dy, self.max[2] + dz],
+        }
+    }
+    /** Пересекаются ли объемы (касание не считается) */
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] < other.max[i] && self.max[i] > other.min[i])
+    }
+
+    /** Насколько можно сдвинуть self по оси axis (не больше чем на delta), не войдя в other */
+    fn clip(&self, other: &Aabb, axis: usize, delta: f64) -> f64 {
+        const EPS: f64 = 1e-7;
+        let overlaps = (0..3).filter(|i| *i != axis).all(|i| self.min[i] < other.max[i] && self.max[i] > other.min[i]);
+        if !overlaps { return delta; }
+        if delta > 0.0 && other.min[axis] >= self.max[axis] - EPS {
+            delta.min(other.min[axis] - self.max[axis]).max(0.0)
+        } else if delta < 0.0 && other.max[axis] <= self.min[axis] + EPS {
+            delta.max(other.max[axis] - self.min[axis]).min(0.0)
+        } else {
+            delta
+        }
+    }
+}
+
+/** Управление игроком на один шаг физики */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MoveInput {
+    /** Движение вперед и вправо, от -1 до 1 */
+    pub forward: f64,
+    pub right: f64,
+    /** Прыжок (в полете - подъем) */
+    pub jump: bool,
+    /** Подкрадывание (в полете - спуск) */
+    pub sneak: bool,
+    /** Бег (в полете - быстрый полет) */
+    pub sprint: bool,
+}
+
+impl BlockModel {
+    /** Границы вершин модели относительно центра блока при соединениях с соседями connections.
+        None, если вершин нет или с блоком нельзя столкнуться */
+    pub fn bounds(&self, connections: u8) -> Option<Aabb> {
+        if !self.collision { return None; }
+        let mut result: Option<Aabb> = None;
+        for shape in self.shapes.iter().flatten().filter(|s| s.is_shown(0, connections)) {
+            for v in shape.vertices.iter() {
+                let pos = v.pos;
+                let p = [pos.x() as f64, pos.y() as f64, pos.z() as f64];
+                let b = result.get_or_insert(Aabb::new(p, p));
+                for (i, v) in p.iter().enumerate() {
+                    b.min[i] = b.min[i].min(*v);
+                    b.max[i] = b.max[i].max(*v);
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Game {
+    /** Объем, с которым сталкивается игрок, в клетке x, y, z (мировые координаты).
+        Блоки сталкиваются по границам своей модели. Незагруженные чанки считаются сплошными,
+        чтобы игрок не проваливался, пока они не загрузятся */
+    pub fn collision_box(&self, x: i32, y: i32, z: i32) -> Option<Aabb> {
+        let b = match self.block_at(x, y, z) {
+            None => return Some(Aabb::new([-0.5; 3], [0.5; 3]).offset(x as f64, y as f64, z as f64)),
+            Some((0, _)) => return None,
+            Some((block, state)) => self.block_model(block, state).bounds(self.block_connections(x, y, z))?,
+        };
+        Some(b.offset(x as f64, y as f64, z as f64))
+    }
+}
+
+impl Player {
+    /** Объем, занимаемый игроком */
+    pub fn aabb(&self) -> Aabb {
+        let w = PLAYER_WIDTH / 2.0;
+        let feet = self.z - PLAYER_EYE_HEIGHT;
+        Aabb::new([self.x - w, self.y - w, feet], [self.x + w, self.y + w, feet + PLAYER_HEIGHT])
+    }
+
+    /** Продвинуть игрока на dt секунд. collision возвращает объем блока в клетке (мировые координаты).
+        В полете столкновений нет */
+    pub fn physics_step<F>(&mut self, input: MoveInput, dt: f64, collision: F)
+        where F: Fn(i32, i32, i32) -> Option<Aabb>
+    {
+        let steps = (dt / MAX_STEP).ceil().max(1.0) as usize;
+        for _ in 0..steps {
+            self.physics_substep(input, dt / steps as f64, &collision);
+        }
+    }
+
+    fn physics_substep<F>(&mut self, input: MoveInput, dt: f64, collision: &F)
+        where F: Fn(i32, i32, i32) -> Option<Aabb>
+    {
+        //Направления вперед и вправо в горизонтальной плоскости
+        let (sin, cos) = self.ang_horz.sin_cos();
+        let wish = [sin * input.forward + cos * input.right, cos * input.forward - sin * input.right];
+        let wish_len = (wish[0] * wish[0] + wish[1] * wish[1]).sqrt();
+        let wish = if wish_len > 1.0 { [wish[0] / wish_len, wish[1] / wish_len] } else { wish };
+
+        if self.fly {
+            let speed = if input.sprint { FLY_FAST_SPEED } else { FLY_SPEED };
+            let up = (input.jump as i32 - input.sneak as i32) as f64;
+            self.x += wish[0] * speed * dt;
+            self.y += wish[1] * speed * dt;
+            self.z += up * speed * dt;
+            self.velocity = [0.0; 3];
+            self.on_ground = false;
+            return;
+        }
+
+        //Скорость плавно подстраивается под желаемую, это и есть трение
+        let speed = if input.sneak { SNEAK_SPEED } else if input.sprint { SPRINT_SPEED } else { WALK_SPEED };
+        let acceleration = if self.on_ground { GROUND_ACCELERATION } else { AIR_ACCELERATION };
+        let k = (acceleration * dt).min(1.0);
+        self.velocity[0] += (wish[0] * speed - self.velocity[0]) * k;
+        self.velocity[1] += (wish[1] * speed - self.velocity[1]) * k;
+
+        if input.jump && self.on_ground {
+            self.velocity[2] = JUMP_SPEED;
+        }
+        self.velocity[2] = (self.velocity[2] - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+        let mut delta = [self.velocity[0] * dt, self.velocity[1] * dt, self.velocity[2] * dt];
+        let body = self.aabb();
+        let boxes = collect_boxes(&body, delta, collision);
+
+        //Подкрадывание не дает сойти с края: сдвиг урезается, пока под игроком остается опора
+        if input.sneak && self.on_ground {
+            let supported = |dx: f64, dy: f64| {
+                let test = body.offset(dx, dy, -STEP_HEIGHT);
+                boxes.iter().any(|b| b.intersects(&test))
+            };
+            const SNEAK_STEP: f64 = 0.01;
+            while delta[0] != 0.0 && !supported(delta[0], 0.0) {
+                delta[0] = if delta[0].abs() < SNEAK_STEP { 0.0 } else { delta[0] - SNEAK_STEP * delta[0].signum() };
+            }
+            while delta[1] != 0.0 && !supported(0.0, delta[1]) {
+                delta[1] = if delta[1].abs() < SNEAK_STEP { 0.0 } else { delta[1] - SNEAK_STEP * delta[1].signum() };
+            }
+            while delta[0] != 0.0 && delta[1] != 0.0 && !supported(delta[0], delta[1]) {
+                delta[0] = if delta[0].abs() < SNEAK_STEP { 0.0 } else { delta[0] - SNEAK_STEP * delta[0].signum() };
+                delta[1] = if delta[1].abs() < SNEAK_STEP { 0.0 } else { delta[1] - SNEAK_STEP * delta[1].signum() };
+            }
+        }
+
+        let mut moved = move_body(&body, delta, &boxes);
+
+        //Заход на уступ: если на земле уперлись в стену, пробуем то же движение, приподнявшись на STEP_HEIGHT
+        let blocked_horizontally = moved[0] != delta[0] || moved[1] != delta[1];
+        let landing = delta[2] < 0.0 && moved[2] != delta[2];
+        if blocked_horizontally && (self.on_ground || landing) {
+            let step_boxes = collect_boxes(&body, [delta[0], delta[1], STEP_HEIGHT], collision);
+            let up = move_body(&body, [0.0, 0.0, STEP_HEIGHT], &step_boxes);
+            let raised = body.offset(up[0], up[1], up[2]);
+            let side = move_body(&raised, [delta[0], delta[1], 0.0], &step_boxes);
+            let shifted = raised.offset(side[0], side[1], 0.0);
+            let down = move_body(&shifted, [0.0, 0.0, -up[2]], &step_boxes);
+            let stepped = [side[0], side[1], up[2] + down[2]];
+            if stepped[0] * stepped[0] + stepped[1] * stepped[1] > moved[0] * moved[0] + moved[1] * moved[1] + 1e-9 {
+                moved = stepped;
+                delta[2] = moved[2];
+                self.velocity[2] = 0.0;
+            }
+        }
+
+        for i in 0..2 {
+            if moved[i] != delta[i] { self.velocity[i] = 0.0; }
+        }
+        //На земле - если движение вниз во что-то уперлось
+        let blocked_down = delta[2] < 0.0 && moved[2] != delta[2];
+        let stepped_up = delta[2] == moved[2] && moved[2] > 0.0 && self.velocity[2] == 0.0;
+        self.on_ground = blocked_down || stepped_up;
+        if moved[2] != delta[2] { self.velocity[2] = 0.0; }
+
+        self.x += moved[0];
+        self.y += moved[1];
+        self.z += moved[2];
+    }
+}
+
+/** Объемы блоков в области, которую может задеть body при сдвиге на delta */
+fn collect_boxes<F>(body: &Aabb, delta: [f64; 3], collision: &F) -> Vec<Aabb>
+    where F: Fn(i32, i32, i32) -> Option<Aabb>
+{
+    let mut range = [(0i32, 0i32); 3];
+    for i in 0..3 {
+        let lo = body.min[i] + delta[i].min(0.0);
+        let hi = body.max[i] + delta[i].max(0.0);
+        //Блок занимает [p - 0.5; p + 0.5]. Снизу берется запас в блок для моделей выше клетки
+        range[i] = ((lo + 0.5).floor() as i32 - if i == 2 { 1 } else { 0 }, (hi + 0.5).floor() as i32);
+    }
+    let mut boxes = vec![];
+    for x in range[0].0..=range[0].1 {
+        for y in range[1].0..=range[1].1 {
+            for z in range[2].0..=range[2].1 {
+                if let Some(b) = collision(x, y, z) { boxes.push(b); }
+            }
+        }
+    }
+    boxes
+}
+
+/** Сдвинуть объем на delta с учетом столкновений (сначала по z, затем по x и y). Возвращает фактический сдвиг */
+fn move_body(body: &Aabb, delta: [f64; 3], boxes: &[Aabb]) -> [f64; 3] {
+    let mut body = *body;
+    let mut moved = [0.0; 3];
+    for axis in [2, 0, 1] {
+        let mut d = delta[axis];
+        for b in boxes.iter() {
+            d = body.clip(b, axis, d);
+        }
+        let mut offset = [0.0; 3];
+        offset[axis] = d;
+        body = body.offset(offset[0], offset[1], offset[2]);
+        moved[axis] = d;
+    }
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, BlockId, Chunk, CHUNK_SIZE};
+    use super::*;
+
+    //Пол из кубов: верх клеток z = 0 на высоте 0.5
+    fn floor(x: i32, y: i32, z: i32) -> Option<Aabb> {
+        if z <= 0 { Some(Aabb::new([-0.5; 3], [0.5; 3]).offset(x as f64, y as f64, z as f64)) } else { None }
+    }
+
+    fn player_at(x: f64, y: f64, feet: f64) -> Player {
+        let mut p = Player::new();
+        (p.x, p.y, p.z) = (x, y, feet + PLAYER_EYE_HEIGHT);
+        p
+    }
+
+    fn feet(p: &Player) -> f64 { p.z - PLAYER_EYE_HEIGHT }
+
+    #[test]
+    fn falls_onto_ground() {
+        let mut p = player_at(0.0, 0.0, 5.0);
+        for _ in 0..100 { p.physics_step(MoveInput::default(), 0.05, floor); }
+        assert!(p.on_ground);
+        assert!((feet(&p) - 0.5).abs() < 1e-6, "feet {}", feet(&p));
+        assert_eq!(p.velocity[2], 0.0);
+    }
+
+    #[test]
+    fn steps_up_onto_slab() {
+        //Нижняя половина плиты в клетках y >= 2, z = 1: верх на высоте 1.0
+        let world = |x: i32, y: i32, z: i32| {
+            if z == 1 && y >= 2 {
+                Some(Aabb::new([-0.5, -0.5, -0.5], [0.5, 0.5, 0.0]).offset(x as f64, y as f64, z as f64))
+            } else {
+                floor(x, y, z)
+            }
+        };
+        let mut p = player_at(0.0, 0.0, 0.5);
+        p.on_ground = true;
+        let input = MoveInput { forward: 1.0, ..Default::default() };
+        for _ in 0..40 { p.physics_step(input, 0.05, world); }
+        assert!(p.y > 3.0, "stuck at y {}", p.y);
+        assert!(p.on_ground);
+        assert!((feet(&p) - 1.0).abs() < 1e-6, "feet {}", feet(&p));
+    }
+
+    #[test]
+    fn steps_up_onto_slab_in_chunk() {
+        //То же, но объемы берутся из моделей блоков настоящего чанка: каменный пол z = 0, плиты в клетках y >= 8, z = 1
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        let slab = game.get_block_id("stone_slab".into()).unwrap() as BlockId;
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                game.set_block(3, x, y, 0);
+                if y >= 8 { game.set_block(slab, x, y, 1); }
+            }
+        }
+        let mut p = player_at(5.0, 5.0, 0.5);
+        p.on_ground = true;
+        let input = MoveInput { forward: 1.0, ..Default::default() };
+        for _ in 0..40 { p.physics_step(input, 0.05, |x, y, z| game.collision_box(x, y, z)); }
+        assert!(p.y > 9.0, "stuck at y {}", p.y);
+        assert!(p.on_ground);
+        assert!((feet(&p) - 1.0).abs() < 1e-6, "feet {}", feet(&p));
+    }
+
+    #[test]
+    fn sneak_stops_at_ledge() {
+        //Пол только до y = 0.5
+        let world = |x: i32, y: i32, z: i32| if y <= 0 { floor(x, y, z) } else { None };
+        let mut p = player_at(0.0, 0.0, 0.5);
+        p.on_ground = true;
+        let input = MoveInput { forward: 1.0, sneak: true, ..Default::default() };
+        for _ in 0..60 { p.physics_step(input, 0.05, world); }
+        //Игрок дошел до края, но опора под ним осталась
+        assert!(p.y > 0.5, "did not reach the edge: y {}", p.y);
+        assert!(p.aabb().min[1] < 0.5);
+        assert!(p.on_ground);
+        assert!((feet(&p) - 0.5).abs() < 1e-6);
+
+        //Без подкрадывания игрок падает с края
+        let input = MoveInput { forward: 1.0, ..Default::default() };
+        for _ in 0..20 { p.physics_step(input, 0.05, world); }
+        assert!(feet(&p) < 0.0);
+    }
+
+    #[test]
+    fn jump_height() {
+        let mut p = player_at(0.0, 0.0, 0.5);
+        p.on_ground = true;
+        p.physics_step(MoveInput { jump: true, ..Default::default() }, MAX_STEP, floor);
+        let mut top = feet(&p);
+        for _ in 0..100 {
+            p.physics_step(MoveInput::default(), MAX_STEP, floor);
+            top = top.max(feet(&p));
+        }
+        //v^2 / 2g = 1.26: чуть больше блока, но меньше полутора
+        let height = top - 0.5;
+        assert!((height - JUMP_SPEED * JUMP_SPEED / (2.0 * GRAVITY)).abs() < 0.1, "height {height}");
+        assert!(height > 1.0 && height < 1.5);
+        assert!(p.on_ground);
+        assert!((feet(&p) - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,200 @@
+use crate::game::{BlockFace, BlockId, BlockModel, BlockState, Game};
+use crate::mat::Vec3;
+
+/** Результат луча: первый блок, в который он уперся */
+#[derive(Clone, Copy, Debug)]
+pub struct RayHit {
+    pub block: BlockId,
+    pub state: BlockState,
+    /** Мировые координаты блока */
+    pub pos: (i32, i32, i32),
+    /** Сторона блока, в которую попал луч */
+    pub face: BlockFace,
+    /** Точка попадания */
+    pub point: Vec3,
+    /** Расстояние от начала луча до точки попадания */
+    pub distance: f32,
+    /** Клетка перед стороной попадания (туда ставится новый блок) */
+    pub adjacent: (i32, i32, i32),
+}
+
+impl Game {
+    /** Найти первый непустой блок на луче (обход клеток Amanatides-Woo).
+        Блоки со всеми сплошными сторонами считаются кубами, остальные проверяются по треугольникам модели.
+        Незагруженные чанки пропускаются как воздух */
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32) -> Option<RayHit> {
+        let len = direction.len();
+        if len == 0.0 || len.is_nan() { return None; }
+        let dir = direction / len;
+        let o = [origin.x(), origin.y(), origin.z()];
+        let d = [dir.x(), dir.y(), dir.z()];
+
+        //Блок занимает [p - 0.5; p + 0.5], поэтому клетка точки - floor(p + 0.5)
+        let mut cell = [0i32; 3];
+        let mut step = [0i32; 3];
+        let mut t_max = [f32::INFINITY; 3];     //Расстояние до следующей границы клетки по каждой оси
+        let mut t_delta = [f32::INFINITY; 3];   //Расстояние между границами по каждой оси
+        for i in 0..3 {
+            cell[i] = (o[i] + 0.5).floor() as i32;
+            if d[i] > 0.0 {
+                step[i] = 1;
+                t_max[i] = (cell[i] as f32 + 0.5 - o[i]) / d[i];
+                t_delta[i] = 1.0 / d[i];
+            } else if d[i] < 0.0 {
+                step[i] = -1;
+                t_max[i] = (cell[i] as f32 - 0.5 - o[i]) / d[i];
+                t_delta[i] = -1.0 / d[i];
+            }
+        }
+
+        //Начало луча внутри блока: сторона - та, что смотрит навстречу лучу по главной оси
+        let main_axis = (0..3).max_by(|a, b| d[*a].abs().total_cmp(&d[*b].abs())).unwrap();
+        let mut entry_face = axis_face(main_axis, d[main_axis] < 0.0);
+        let mut t_enter = 0.0f32;
+
+        while t_enter <= max_distance {
+            let t_exit = t_max[0].min(t_max[1]).min(t_max[2]).min(max_distance);
+            if let Some((block, state)) = self.block_at(cell[0], cell[1], cell[2]) {
+                if block != 0 {
+                    let model = self.block_model(block, state);
+                    let hit = if model.solid_faces().0 & 0b00111111 == 0b00111111 {
+                        Some((t_enter, entry_face))
+                    } else {
+                        let connections = self.block_connections(cell[0], cell[1], cell[2]);
+                        hit_model(model, connections, cell, origin, dir, t_enter, t_exit)
+                    };
+                    if let Some((t, face)) = hit {
+                        let pos = (cell[0], cell[1], cell[2]);
+                        let offset = face.offset();
+                        return Some(RayHit {
+                            block, state, pos, face,
+                            point: origin + dir * t,
+                            distance: t,
+                            adjacent: (pos.0 + offset.0, pos.1 + offset.1, pos.2 + offset.2),
+                        });
+                    }
+                }
+            }
+
+            //Переход в соседнюю клетку через ближайшую границу
+            let axis = if t_max[0] < t_max[1] {
+                if t_max[0] < t_max[2] { 0 } else { 2 }
+            } else if t_max[1] < t_max[2] { 1 } else { 2 };
+            if t_max[axis] == f32::INFINITY { break; }
+            t_enter = t_max[axis];
+            cell[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+            entry_face = axis_face(axis, step[axis] < 0);
+        }
+        None
+    }
+}
+
+/** Сторона блока по оси (0 - x, 1 - y, 2 - z) и направлению нормали */
+fn axis_face(axis: usize, positive: bool) -> BlockFace {
+    BlockFace::ALL[axis * 2 + if positive { 0 } else { 1 }]
+}
+
+/** Ближайшее пересечение луча с треугольниками модели (при соединениях с соседями connections) в пределах [t_min; t_max] */
+fn hit_model(model: &BlockModel, connections: u8, cell: [i32; 3], origin: Vec3, dir: Vec3, t_min: f32, t_max: f32) -> Option<(f32, BlockFace)> {
+    const EPS: f32 = 1e-5;
+    let center = Vec3::new(cell[0] as f32, cell[1] as f32, cell[2] as f32);
+    let mut best: Option<(f32, BlockFace)> = None;
+    for [a, b, c] in model.triangles(connections) {
+        let (a, b, c) = (a + center, b + center, c + center);
+        let t = match intersect_triangle(origin, dir, a, b, c) {
+            Some(t) if t >= t_min - EPS && t <= t_max + EPS => t,
+            _ => continue,
+        };
+        if best.is_some_and(|(best_t, _)| best_t <= t) { continue; }
+
+        //Сторона по главной оси нормали треугольника, повернутой навстречу лучу
+        let n = cross(b - a, c - a);
+        let n = if n * dir > 0.0 { n * -1.0 } else { n };
+        let n = [n.x(), n.y(), n.z()];
+        let axis = (0..3).max_by(|i, j| n[*i].abs().total_cmp(&n[*j].abs())).unwrap();
+        best = Some((t.max(t_min), axis_face(axis, n[axis] > 0.0)));
+    }
+    best
+}
+
+/** Пересечение луча с треугольником (Моллер-Трумбор), с обеих сторон треугольника */
+fn intersect_triangle(origin: Vec3, dir: Vec3, a: Vec3, b: Vec3, c: Vec3) -> Option<f32> {
+    let (e1, e2) = (b - a, c - a);
+    let p = cross(dir, e2);
+    let det = e1 * p;
+    if det.abs() < 1e-8 { return None; }
+    let inv = 1.0 / det;
+    let s = origin - a;
+    let u = (s * p) * inv;
+    if !(0.0..=1.0).contains(&u) { return None; }
+    let q = cross(s, e1);
+    let v = (dir * q) * inv;
+    if v < 0.0 || u + v > 1.0 { return None; }
+    let t = (e2 * q) * inv;
+    if t < 0.0 { None } else { Some(t) }
+}
+
+pub(super) fn cross(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(
+        a.y() * b.z() - a.z() * b.y(),
+        a.z() * b.x() - a.x() * b.z(),
+        a.x() * b.y() - a.y() * b.x())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, BlockId, Chunk};
+    use super::*;
+
+    const EPS: f32 = 1e-4;
+
+    fn game_with(block: &str, pos: (i32, i32, i32)) -> (Game, BlockId) {
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        let id = game.get_block_id(block.into()).unwrap() as BlockId;
+        game.set_block(id, pos.0, pos.1, pos.2);
+        (game, id)
+    }
+
+    #[test]
+    fn cube_face() {
+        let (game, stone) = game_with("stone", (3, 1, 1));
+        let hit = game.raycast(Vec3::new(0.0, 1.2, 0.9), Vec3::new(1.0, 0.0, 0.0), 10.0).unwrap();
+        assert_eq!((hit.block, hit.pos, hit.face, hit.adjacent), (stone, (3, 1, 1), BlockFace::NX, (2, 1, 1)));
+        assert!((hit.point - Vec3::new(2.5, 1.2, 0.9)).len() < EPS);
+        assert!((hit.distance - 2.5).abs() < EPS);
+
+        //Сверху, под углом
+        let hit = game.raycast(Vec3::new(1.0, 1.0, 3.5), Vec3::new(1.0, 0.0, -1.0), 10.0).unwrap();
+        assert_eq!((hit.pos, hit.face, hit.adjacent), ((3, 1, 1), BlockFace::PZ, (3, 1, 2)));
+        assert!((hit.point - Vec3::new(3.0, 1.0, 1.5)).len() < EPS);
+
+        assert!(game.raycast(Vec3::new(0.0, 1.2, 0.9), Vec3::new(1.0, 0.0, 0.0), 2.4).is_none());
+        assert!(game.raycast(Vec3::new(0.0, 1.2, 0.9), Vec3::new(-1.0, 0.0, 0.0), 10.0).is_none());
+    }
+
+    #[test]
+    fn cyl_low_corner_is_empty() {
+        //Луч проходит через угол клетки, x + y = 0.9, а восьмиугольник цилиндра заканчивается на x + y = sqrt(2) / 2
+        let (origin, dir) = (Vec3::new(-1.55, 2.45, 0.0), Vec3::new(1.0, -1.0, 0.0));
+        let (game, _) = game_with("log_cyl", (0, 0, 0));
+        assert!(game.raycast(origin, dir, 10.0).is_none());
+
+        //Куб на том же месте луч задевает
+        let (game, _) = game_with("stone", (0, 0, 0));
+        assert_eq!(game.raycast(origin, dir, 10.0).map(|hit| hit.pos), Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn cyl_low_side() {
+        let (game, log) = game_with("log_cyl", (0, 0, 0));
+        let hit = game.raycast(Vec3::new(0.2, -3.0, 0.1), Vec3::new(0.0, 1.0, 0.0), 10.0).unwrap();
+        assert_eq!((hit.block, hit.pos, hit.face, hit.adjacent), (log, (0, 0, 0), BlockFace::NY, (0, -1, 0)));
+        //Грань между (0, -0.5) и (sqrt(2) / 4, -sqrt(2) / 4) на x = 0.2
+        let s2 = 2.0f32.sqrt() / 4.0;
+        let y = -0.5 + 0.2 * (0.5 - s2) / s2;
+        assert!((hit.point - Vec3::new(0.2, y, 0.1)).len() < EPS, "{:?}", hit.point);
+        assert!((hit.distance - (3.0 + y)).abs() < EPS);
+    }
+}
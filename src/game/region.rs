@@ -0,0 +1,380 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use flate2::Compression;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use crate::game::{BlockId, BlockState, Chunk, ChunkPos, DenseBools, CHUNK_SIZE, CHUNK_VOLUME};
+
+/** Сторона региона в чанках. Регион - куб из REGION_SIZE^3 чанков, хранящийся в одном файле */
+pub const REGION_SIZE: i32 = 8;
+pub const REGION_VOLUME: usize = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as usize;
+
+const REGION_MAGIC: &[u8; 4] = b"LCRG";
+/** Версия формата. Увеличивается при любом несовместимом изменении */
+pub const REGION_VERSION: u32 = 1;
+
+/** Координаты региона (в регионах) */
+pub type RegionPos = (i32, i32, i32);
+
+/** Регион, содержащий чанк */
+pub fn region_pos_of(chunk: ChunkPos) -> RegionPos {
+    (chunk.0.div_euclid(REGION_SIZE), chunk.1.div_euclid(REGION_SIZE), chunk.2.div_euclid(REGION_SIZE))
+}
+/** Номер чанка внутри региона */
+fn region_slot(chunk: ChunkPos) -> usize {
+    let (x, y, z) = (chunk.0.rem_euclid(REGION_SIZE), chunk.1.rem_euclid(REGION_SIZE), chunk.2.rem_euclid(REGION_SIZE));
+    (z * REGION_SIZE * REGION_SIZE + y * REGION_SIZE + x) as usize
+}
+
+#[derive(Debug)]
+pub enum WorldError {
+    Io(io::Error),
+    /** Файл не является файлом региона */
+    BadMagic,
+    UnsupportedVersion(u32),
+    /** Данные повреждены (описание) */
+    Corrupted(String),
+}
+impl From<io::Error> for WorldError {
+    fn from(other: io::Error) -> Self { WorldError::Io(other) }
+}
+
+/** Файл региона.
+    Формат (все числа little endian):
+        "LCRG", версия u32
+        палитра названий блоков: количество u32, затем для каждого длина u16 и байты utf8
+        индекс: REGION_VOLUME пар (смещение u32, длина u32) от начала файла, длина 0 - чанка нет
+        сжатые zlib данные чанков
+    Данные чанка до сжатия - серии одинаковых блоков в порядке Chunk::pos_id:
+        длина серии u16, номер названия в палитре u16, состояние u16 */
+pub struct RegionFile {
+    names: Vec<String>,
+    chunks: HashMap<usize, Vec<u8>>,    //Сжатые данные по номеру чанка в регионе
+}
+impl RegionFile {
+    pub fn new() -> Self { Self { names: vec![], chunks: HashMap::new() } }
+
+    /** Прочитать регион. Если файла нет - регион пустой */
+    pub fn read(path: &PathBuf) -> Result<Self, WorldError> {
+        match fs::read(path) {
+            Ok(bytes) => Self::from_bytes(&bytes),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+    /** Записать регион. Файл сначала пишется рядом, затем заменяет старый */
+    pub fn write(&self, path: &PathBuf) -> Result<(), WorldError> {
+        if let Some(dir) = path.parent() { fs::create_dir_all(dir)?; }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.to_bytes())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WorldError> {
+        let mut reader = ByteReader { bytes, pos: 0 };
+        if reader.take(4)? != REGION_MAGIC { return Err(WorldError::BadMagic); }
+        let version = reader.u32()?;
+        if version != REGION_VERSION { return Err(WorldError::UnsupportedVersion(version)); }
+
+        let names_count = reader.u32()? as usize;
+        let mut names = Vec::with_capacity(names_count);
+        for _ in 0..names_count {
+            let len = reader.u16()? as usize;
+            let name = String::from_utf8(reader.take(len)?.to_vec())
+                .map_err(|_| WorldError::Corrupted("block name is not utf8".into()))?;
+            names.push(name);
+        }
+
+        let mut chunks = HashMap::new();
+        for slot in 0..REGION_VOLUME {
+            let offset = reader.u32()? as usize;
+            let len = reader.u32()? as usize;
+            if len == 0 { continue; }
+            let data = bytes.get(offset..offset + len)
+                .ok_or_else(|| WorldError::Corrupted(format!("chunk {} is out of file", slot)))?;
+            chunks.insert(slot, data.to_vec());
+        }
+        Ok(Self { names, chunks })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header: Vec<u8> = vec![];
+        header.extend_from_slice(REGION_MAGIC);
+        header.extend_from_slice(&REGION_VERSION.to_le_bytes());
+        header.extend_from_slice(&(self.names.len() as u32).to_le_bytes());
+        for name in self.names.iter() {
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(name.as_bytes());
+        }
+
+        let mut offset = header.len() + REGION_VOLUME * 8;
+        let mut data: Vec<u8> = vec![];
+        for slot in 0..REGION_VOLUME {
+            let len = self.chunks.get(&slot).map(|c| c.len()).unwrap_or(0);
+            header.extend_from_slice(&(if len > 0 { offset as u32 } else { 0 }).to_le_bytes());
+            header.extend_from_slice(&(len as u32).to_le_bytes());
+            if let Some(c) = self.chunks.get(&slot) {
+                data.extend_from_slice(c);
+                offset += len;
+            }
+        }
+        header.append(&mut data);
+        header
+    }
+
+    pub fn contains(&self, pos: ChunkPos) -> bool { self.chunks.contains_key(&region_slot(pos)) }
+    pub fn chunks_count(&self) -> usize { self.chunks.len() }
+    /** Названия блоков, встречающихся в регионе */
+    pub fn names(&self) -> &Vec<String> { &self.names }
+
+    /** Сохранить чанк в регион. block_names - названия блоков по их номерам */
+    pub fn put_chunk(&mut self, chunk: &Chunk, block_names: &[String]) {
+        let mut raw: Vec<u8> = vec![];
+        let mut write_run = |len: u16, block: BlockId, state: BlockState, names: &mut Vec<String>| {
+            let name = &block_names[block as usize];
+            let name_id = match names.iter().position(|n| n == name) {
+                Some(id) => id,
+                None => { names.push(name.clone()); names.len() - 1 }
+            };
+            raw.extend_from_slice(&len.to_le_bytes());
+            raw.extend_from_slice(&(name_id as u16).to_le_bytes());
+            raw.extend_from_slice(&state.to_le_bytes());
+        };
+
+        let mut run: (u16, BlockId, BlockState) = (0, 0, 0);
+        for id in 0..CHUNK_VOLUME {
+            let (x, y, z) = pos_of_id(id);
+            let current = (chunk.block(x, y, z), chunk.state(x, y, z));
+            if run.0 > 0 && (run.1, run.2) != current {
+                write_run(run.0, run.1, run.2, &mut self.names);
+                run.0 = 0;
+            }
+            run = (run.0 + 1, current.0, current.1);
+        }
+        write_run(run.0, run.1, run.2, &mut self.names);
+
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&raw).unwrap();
+        self.chunks.insert(region_slot(chunk.pos()), encoder.finish().unwrap());
+    }
+
+    /** Загрузить чанк из региона.
+        block_id - текущий номер блока по названию (неизвестные блоки становятся воздухом),
+        solid_faces - сплошные стороны блока, нужны для пересчета закрытости сторон */
+    pub fn get_chunk<F, S>(&self, pos: ChunkPos, block_id: F, solid_faces: S) -> Result<Option<Chunk>, WorldError>
+        where F: Fn(&str) -> Option<BlockId>, S: Fn(BlockId, BlockState) -> DenseBools
+    {
+        let data = match self.chunks.get(&region_slot(pos)) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let mut raw = vec![];
+        ZlibDecoder::new(&data[..]).read_to_end(&mut raw)
+            .map_err(|e| WorldError::Corrupted(format!("chunk {:?}: {}", pos, e)))?;
+
+        //Номера блоков из палитры региона в текущие номера
+        let remap: Vec<BlockId> = self.names.iter().map(|n| block_id(n).unwrap_or(0)).collect();
+
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        let mut reader = ByteReader { bytes: &raw, pos: 0 };
+        let mut id = 0usize;
+        while id < CHUNK_VOLUME {
+            let len = reader.u16()? as usize;
+            let name_id = reader.u16()? as usize;
+            let state = reader.u16()?;
+            let block = *remap.get(name_id)
+                .ok_or_else(|| WorldError::Corrupted(format!("chunk {:?}: unknown palette entry {}", pos, name_id)))?;
+            if len == 0 || id + len > CHUNK_VOLUME {
+                return Err(WorldError::Corrupted(format!("chunk {:?}: wrong run length", pos)));
+            }
+            if block != 0 {
+                let solid = solid_faces(block, state);
+                for i in id..id + len {
+                    let (x, y, z) = pos_of_id(i);
+                    chunk.set_block_state(block, state, x, y, z, solid);
+                }
+            }
+            id += len;
+        }
+        Ok(Some(chunk))
+    }
+}
+impl Default for RegionFile {
+    fn default() -> Self { Self::new() }
+}
+
+/** Папка сохранения мира, регионы лежат в ней файлами "x.y.z.region" */
+#[derive(Clone)]
+pub struct WorldSave {
+    dir: PathBuf,
+}
+impl WorldSave {
+    pub fn new(dir: PathBuf) -> Self { Self { dir } }
+
+    pub fn dir(&self) -> &PathBuf { &self.dir }
+    pub fn region_path(&self, region: RegionPos) -> PathBuf {
+        self.dir.join(format!("{}.{}.{}.region", region.0, region.1, region.2))
+    }
+
+    pub fn read_region(&self, region: RegionPos) -> Result<RegionFile, WorldError> {
+        RegionFile::read(&self.region_path(region))
+    }
+    pub fn write_region(&self, region: RegionPos, file: &RegionFile) -> Result<(), WorldError> {
+        file.write(&self.region_path(region))
+    }
+
+    /** Сохранить чанки, каждый регион читается и перезаписывается один раз */
+    pub fn save_chunks<'a, I>(&self, chunks: I, block_names: &[String]) -> Result<(), WorldError>
+        where I: Iterator<Item = &'a Chunk>
+    {
+        let mut by_region: HashMap<RegionPos, Vec<&Chunk>> = HashMap::new();
+        for chunk in chunks {
+            by_region.entry(region_pos_of(chunk.pos())).or_default().push(chunk);
+        }
+        for (region, chunks) in by_region {
+            let mut file = self.read_region(region)?;
+            for chunk in chunks {
+                file.put_chunk(chunk, block_names);
+            }
+            self.write_region(region, &file)?;
+        }
+        Ok(())
+    }
+}
+
+fn pos_of_id(id: usize) -> (i32, i32, i32) {
+    ((id % CHUNK_SIZE) as i32, ((id / CHUNK_SIZE) % CHUNK_SIZE) as i32, (id / (CHUNK_SIZE * CHUNK_SIZE)) as i32)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], WorldError> {
+        let res = self.bytes.get(self.pos..self.pos + len)
+            .ok_or_else(|| WorldError::Corrupted("unexpected end of data".into()))?;
+        self.pos += len;
+        Ok(res)
+    }
+    fn u16(&mut self) -> Result<u16, WorldError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+    fn u32(&mut self) -> Result<u32, WorldError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::BlockFace;
+    use super::*;
+
+    fn names() -> Vec<String> { ["air", "dirt", "stone", "log"].iter().map(|n| n.to_string()).collect() }
+
+    fn sample_chunk(pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                chunk.set_block(2, x, y, 0, DenseBools(63));
+                chunk.set_block(1, x, y, 1, DenseBools(63));
+            }
+        }
+        chunk.set_block_state(3, 2, 5, 6, 7, DenseBools(63));
+        chunk
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut region = RegionFile::new();
+        region.put_chunk(&sample_chunk((0, 0, 0)), &names());
+        region.put_chunk(&sample_chunk((-1, 3, -8)), &names());
+        let bytes = region.to_bytes();
+
+        let read = RegionFile::from_bytes(&bytes).unwrap();
+        assert_eq!(read.names(), region.names());
+        assert_eq!(read.chunks_count(), 2);
+        assert!(read.contains((0, 0, 0)) && read.contains((-1, 3, -8)));
+        assert!(!read.contains((1, 0, 0)));
+        assert_eq!(read.to_bytes(), bytes);
+        assert_eq!(RegionFile::from_bytes(&RegionFile::new().to_bytes()).unwrap().chunks_count(), 0);
+    }
+
+    #[test]
+    fn chunk_round_trip_remaps_ids() {
+        let mut region = RegionFile::new();
+        region.put_chunk(&sample_chunk((1, 2, 3)), &names());
+        assert!(RegionFile::new().get_chunk((1, 2, 3), |_| None, |_, _| DenseBools(0)).unwrap().is_none());
+
+        //В новом реестре другие номера, а бревна нет совсем
+        let block_id = |name: &str| match name {
+            "air" => Some(0),
+            "stone" => Some(5),
+            "dirt" => Some(7),
+            _ => None,
+        };
+        let chunk = region.get_chunk((1, 2, 3), block_id, |_, _| DenseBools(63)).unwrap().unwrap();
+        assert_eq!(chunk.pos(), (1, 2, 3));
+        assert_eq!(chunk.block(4, 9, 0), 5);
+        assert_eq!(chunk.block(4, 9, 1), 7);
+        assert_eq!(chunk.block(4, 9, 2), 0);
+        assert_eq!((chunk.block(5, 6, 7), chunk.state(5, 6, 7)), (0, 0));
+        assert_eq!(chunk.blocks_count(), 2 * CHUNK_SIZE * CHUNK_SIZE);
+        //Закрытость сторон пересчитана по сплошным сторонам блоков
+        assert!(chunk.faces(4, 9, 0).get(BlockFace::PZ.into()));
+        assert!(!chunk.faces(4, 9, 1).get(BlockFace::PZ.into()));
+
+        //Состояния сохраняются, если блок известен
+        let chunk = region.get_chunk((1, 2, 3), |n| names().iter().position(|m| m == n).map(|i| i as BlockId), |_, _| DenseBools(63))
+            .unwrap().unwrap();
+        assert_eq!((chunk.block(5, 6, 7), chunk.state(5, 6, 7)), (3, 2));
+    }
+
+    #[test]
+    fn corrupted_input() {
+        let mut region = RegionFile::new();
+        region.put_chunk(&sample_chunk((0, 0, 0)), &names());
+        let bytes = region.to_bytes();
+
+        for len in [0, 3, 10, bytes.len() / 2, bytes.len() - 1] {
+            assert!(matches!(RegionFile::from_bytes(&bytes[..len]), Err(WorldError::Corrupted(_))), "truncated to {}", len);
+        }
+        assert!(matches!(RegionFile::from_bytes(b"garbage garbage garbage"), Err(WorldError::BadMagic)));
+        let mut wrong_version = bytes.clone();
+        wrong_version[4..8].copy_from_slice(&(REGION_VERSION + 1).to_le_bytes());
+        assert!(matches!(RegionFile::from_bytes(&wrong_version), Err(WorldError::UnsupportedVersion(v)) if v == REGION_VERSION + 1));
+
+        //Заголовок цел, данные чанка испорчены
+        let mut broken = RegionFile::from_bytes(&bytes).unwrap();
+        broken.chunks.insert(region_slot((0, 0, 0)), b"not zlib at all".to_vec());
+        let res = broken.get_chunk((0, 0, 0), |_| Some(1), |_, _| DenseBools(0));
+        assert!(matches!(res, Err(WorldError::Corrupted(_))));
+
+        //Сжатые данные корректны, но серии не покрывают чанк
+        let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+        encoder.write_all(&[1, 0, 0, 0, 0, 0]).unwrap();
+        broken.chunks.insert(region_slot((0, 0, 0)), encoder.finish().unwrap());
+        let res = broken.get_chunk((0, 0, 0), |_| Some(1), |_, _| DenseBools(0));
+        assert!(matches!(res, Err(WorldError::Corrupted(_))));
+    }
+
+    #[test]
+    fn save_and_read_files() {
+        let dir = std::env::temp_dir().join(format!("lotofcubes_region_test_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let save = WorldSave::new(dir.clone());
+        let chunks = [sample_chunk((0, 0, 0)), sample_chunk((9, 0, 0))];
+        save.save_chunks(chunks.iter(), &names()).unwrap();
+
+        assert!(save.read_region((0, 0, 0)).unwrap().contains((0, 0, 0)));
+        assert!(save.read_region((1, 0, 0)).unwrap().contains((9, 0, 0)));
+        assert_eq!(save.read_region((5, 5, 5)).unwrap().chunks_count(), 0);
+
+        fs::write(save.region_path((0, 0, 0)), b"LCRG").unwrap();
+        assert!(matches!(save.read_region((0, 0, 0)), Err(WorldError::Corrupted(_))));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::{HashMap, HashSet};
+use crate::game::{chunk_pos_of, region_pos_of, ChunkPos, Game, RegionFile, RegionPos, WorldError, WorldSave};
+use crate::mat::Vec3;
+
+/** Насколько чанков дальше дальности прорисовки чанки еще остаются загруженными.
+    Без запаса чанки на границе загружались бы и выгружались при каждом шаге туда-обратно */
+pub const STREAM_HYSTERESIS: i32 = 2;
+/** Дальность загрузки по вертикали, в чанках */
+pub const STREAM_VERTICAL_DIST: i32 = 1;
+/** Сколько чанков одновременно может генерироваться. Очередь короткая, чтобы порядок загрузки
+    успевал следовать за игроком и направлением взгляда */
+const MAX_PENDING_CHUNKS: usize = 16;
+
+/** Чанк, в котором находится точка мира */
+pub fn chunk_pos_at(pos: (f64, f64, f64)) -> ChunkPos {
+    let block = |p: f64| (p + 0.5).floor() as i32;
+    chunk_pos_of(block(pos.0), block(pos.1), block(pos.2))
+}
+
+/** Подгрузка чанков вокруг игрока: сохраненные читаются с диска, остальные генерируются в фоне.
+    Далекие чанки выгружаются, измененные игроком перед этим сохраняются.
+    Регион, который не удалось прочитать, больше не трогается: его чанки не загружаются и не генерируются,
+    а файл не перезаписывается, чтобы не потерять сохраненные в нем чанки */
+pub struct ChunkStreamer {
+    save: WorldSave,
+    /** Прочитанные файлы регионов, в которых есть загруженные чанки */
+    regions: HashMap<RegionPos, RegionFile>,
+    /** Регионы, которые не удалось прочитать */
+    broken: HashSet<RegionPos>,
+    /** Дальность прорисовки в чанках (по горизонтали) */
+    render_dist: i32,
+}
+impl ChunkStreamer {
+    pub fn new(save: WorldSave, render_dist: i32) -> Self {
+        Self { save, regions: HashMap::new(), broken: HashSet::new(), render_dist: render_dist.max(1) }
+    }
+
+    pub fn render_dist(&self) -> i32 { self.render_dist }
+    /** Изменить дальность прорисовки. Загруженная область вырастет или уменьшится при следующем update */
+    pub fn set_render_dist(&mut self, render_dist: i32) { self.render_dist = render_dist.max(1); }
+
+    /** Входит ли чанк в область прорисовки вокруг чанка center */
+    pub fn is_in_range(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        let (dx, dy, dz) = (pos.0 - center.0, pos.1 - center.1, pos.2 - center.2);
+        dx * dx + dy * dy <= self.render_dist * self.render_dist && dz.abs() <= STREAM_VERTICAL_DIST
+    }
+
+    /** Файл региона: из кэша или с диска. Если прочитать не удалось, регион запоминается как поврежденный */
+    fn region(&mut self, region_pos: RegionPos) -> Result<&mut RegionFile, WorldError> {
+        if !self.regions.contains_key(&region_pos) {
+            let region = self.save.read_region(region_pos).inspect_err(|_| { self.broken.insert(region_pos); })?;
+            self.regions.insert(region_pos, region);
+        }
+        Ok(self.regions.get_mut(&region_pos).unwrap())
+    }
+
+    /** Загрузить чанк из сохранения сразу. Ok(false), если его там нет */
+    pub fn load_saved(&mut self, game: &mut Game, pos: ChunkPos) -> Result<bool, WorldError> {
+        let region = self.region(region_pos_of(pos))?;
+        match game.read_chunk(region, pos)? {
+            Some(chunk) => {
+                game.add_chunk(chunk);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /** Выгрузить далекие чанки и запросить недостающие вокруг игрока.
+        pos - позиция игрока (мировые координаты), look - направление взгляда.
+        Ближние чанки и чанки перед игроком загружаются первыми.
+        Ошибка чтения или записи возвращается один раз, после этого поврежденный регион пропускается */
+    pub fn update(&mut self, game: &mut Game, pos: (f64, f64, f64), look: Vec3) -> Result<(), WorldError> {
+        let center = chunk_pos_at(pos);
+        self.unload_far(game, center)?;
+
+        //Недостающие чанки в порядке важности: расстояние, увеличенное для чанков за спиной
+        let (r, v) = (self.render_dist, STREAM_VERTICAL_DIST);
+        let look_len = look.len().max(1e-6);
+        let mut missing: Vec<(f32, ChunkPos)> = vec![];
+        for dx in -r..=r {
+            for dy in -r..=r {
+                for dz in -v..=v {
+                    let chunk = (center.0 + dx, center.1 + dy, center.2 + dz);
+                    if !self.is_in_range(center, chunk) || game.is_chunk_loaded(chunk) || game.is_chunk_pending(chunk) ||
+                        self.broken.contains(&region_pos_of(chunk)) {
+                        continue;
+                    }
+                    let offset = Vec3::new(dx as f32, dy as f32, dz as f32);
+                    let dist = offset.len();
+                    let facing = if dist > 0.0 { (offset * look) / (dist * look_len) } else { 1.0 };
+                    missing.push((dist * (1.5 - 0.5 * facing), chunk));
+                }
+            }
+        }
+        missing.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        for (_, chunk) in missing {
+            if game.pending_chunks_count() >= MAX_PENDING_CHUNKS { break; }
+            if self.broken.contains(&region_pos_of(chunk)) { continue; }
+            if !self.load_saved(game, chunk)? {
+                game.request_chunk(chunk);
+            }
+        }
+        Ok(())
+    }
+
+    /** Выгрузить чанки дальше дальности прорисовки с запасом STREAM_HYSTERESIS и отменить их генерацию.
+        Измененные игроком чанки сохраняются. Регионы, в которых не осталось загруженных чанков, убираются из кэша */
+    fn unload_far(&mut self, game: &mut Game, center: ChunkPos) -> Result<(), WorldError> {
+        let keep = self.render_dist + STREAM_HYSTERESIS;
+        let far = |pos: ChunkPos| {
+            let (dx, dy, dz) = (pos.0 - center.0, pos.1 - center.1, pos.2 - center.2);
+            dx * dx + dy * dy > keep * keep || dz.abs() > STREAM_VERTICAL_DIST + STREAM_HYSTERESIS
+        };
+
+        for pos in game.pending_chunks().into_iter().filter(|p| far(*p)) {
+            game.cancel_chunk(pos);
+        }
+
+        let block_names = game.block_names();
+        let mut changed_regions = vec![];
+        let mut result = Ok(());
+        let far_chunks: Vec<ChunkPos> = game.chunks().positions().filter(|p| far(*p)).collect();
+        for pos in far_chunks {
+            let chunk = match game.remove_chunk(pos) {
+                Some(chunk) => chunk,
+                None => continue,
+            };
+            if !chunk.is_edited() { continue; }
+            let region_pos = region_pos_of(pos);
+            //Без прочитанного региона запись затерла бы остальные его чанки, так что изменения чанка теряются
+            match self.region(region_pos) {
+                Ok(region) => region.put_chunk(&chunk, &block_names),
+                Err(e) => {
+                    if result.is_ok() { result = Err(e); }
+                    continue;
+                }
+            }
+            if !changed_regions.contains(&region_pos) { changed_regions.push(region_pos); }
+        }
+        for region_pos in changed_regions {
+            if let Err(e) = self.save.write_region(region_pos, &self.regions[&region_pos]) {
+                if result.is_ok() { result = Err(e); }
+            }
+        }
+
+        let used: HashSet<RegionPos> = game.chunks().positions().map(region_pos_of).collect();
+        self.regions.retain(|pos, _| used.contains(pos));
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use crate::game::{test_game, Chunk, GeneratorPreset};
+    use super::*;
+
+    /** Пустая папка сохранения для теста name */
+    fn save_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lotofcubes_streaming_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn near_chunks_in_view_are_requested_first() {
+        //Очередь генерации короче недостающей области, в нее попадают ближние чанки и чанки перед игроком
+        for (look, ahead) in [(Vec3::new(1.0, 0.0, 0.0), (2, 0, 0)), (Vec3::new(-1.0, 0.0, 0.0), (-2, 0, 0))] {
+            let dir = save_dir("order");
+            let mut game = test_game();
+            let generator = GeneratorPreset::Classic.create(&game).unwrap();
+            game.start_workers(1, generator.into(), 0);
+            let mut streamer = ChunkStreamer::new(WorldSave::new(dir.clone()), 4);
+            streamer.update(&mut game, (0.0, 0.0, 0.0), look).unwrap();
+
+            let pending = game.pending_chunks();
+            assert_eq!(pending.len(), MAX_PENDING_CHUNKS);
+            assert!(pending.contains(&(0, 0, 0)) && pending.contains(&ahead));
+            assert!(!pending.contains(&(-ahead.0, 0, 0)), "{:?}", pending);
+            assert!(pending.iter().all(|p| p.0 * p.0 + p.1 * p.1 + p.2 * p.2 <= 4), "{:?}", pending);
+            let _ = fs::remove_dir_all(&dir);
+        }
+    }
+
+    #[test]
+    fn far_chunks_are_unloaded_with_hysteresis() {
+        let dir = save_dir("hysteresis");
+        let mut game = test_game();
+        //Дальность 2, чанки выгружаются дальше 2 + STREAM_HYSTERESIS по горизонтали и 1 + STREAM_HYSTERESIS по вертикали
+        let kept = [(0, 0, 0), (4, 0, 0), (0, -4, 0), (0, 0, 3), (0, 0, -3)];
+        let unloaded = [(5, 0, 0), (3, 3, 0), (0, 0, 4), (0, 0, -4)];
+        for pos in kept.iter().chain(unloaded.iter()) {
+            game.add_chunk(Chunk::empty(pos.0, pos.1, pos.2));
+        }
+        let mut streamer = ChunkStreamer::new(WorldSave::new(dir.clone()), 2);
+        streamer.update(&mut game, (0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+
+        for pos in kept { assert!(game.is_chunk_loaded(pos), "{:?} выгружен", pos); }
+        for pos in unloaded { assert!(!game.is_chunk_loaded(pos), "{:?} не выгружен", pos); }
+        //Неизмененные чанки не сохраняются
+        assert!(!dir.exists());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn edited_chunks_are_saved_on_unload() {
+        let dir = save_dir("unload");
+        let mut game = test_game();
+        game.add_chunk(Chunk::empty(5, 0, 0));
+        game.add_chunk(Chunk::empty(6, 0, 0));
+        game.set_block(3, 5 * 32 + 4, 7, 9);
+
+        let save = WorldSave::new(dir.clone());
+        let mut streamer = ChunkStreamer::new(save.clone(), 1);
+        streamer.update(&mut game, (0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert!(!game.is_chunk_loaded((5, 0, 0)) && !game.is_chunk_loaded((6, 0, 0)));
+        let region = save.read_region(region_pos_of((5, 0, 0))).unwrap();
+        assert!(region.contains((5, 0, 0)));
+        assert!(!region.contains((6, 0, 0)));
+
+        //Вернувшись, игрок находит чанк таким, каким его оставил
+        streamer.update(&mut game, (5.0 * 32.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)).unwrap();
+        assert_eq!(game.block_at(5 * 32 + 4, 7, 9), Some((3, 0)));
+        assert!(!game.is_chunk_loaded((6, 0, 0)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn broken_region_is_skipped() {
+        let dir = save_dir("broken");
+        let mut game = test_game();
+        let save = WorldSave::new(dir.clone());
+        //Чанк (-1, 0, 0) сохранен в исправном регионе, регион с чанком (0, 0, 0) испорчен
+        let mut saved = Chunk::empty(-1, 0, 0);
+        saved.set_block(3, 1, 2, 3, game.block_solid_faces(3, 0));
+        save.save_chunks([saved].iter(), &game.block_names()).unwrap();
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(save.region_path(region_pos_of((0, 0, 0))), b"broken").unwrap();
+
+        let mut streamer = ChunkStreamer::new(save.clone(), 1);
+        assert!(streamer.update(&mut game, (0.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)).is_err());
+        //Ошибка возвращается один раз, дальше регион пропускается, а остальные чанки загружаются
+        streamer.update(&mut game, (0.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!(!game.is_chunk_loaded((0, 0, 0)));
+        assert_eq!(game.block_at(-32 + 1, 2, 3), Some((3, 0)));
+        //Поврежденный файл не перезаписывается
+        assert_eq!(fs::read(save.region_path(region_pos_of((0, 0, 0)))).unwrap(), b"broken");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -1,6 +1,7 @@
 use std::ops::Add;
 use std::path::PathBuf;
 use crate::game::{BlockFace, BlockModel, BMShape, ShapeVertex, Vertex};
+use crate::mat::Vec3;
 use crate::{Model, rgl};
 
 /** Восемь bool значений, скомпресованные в байт */
@@ -103,60 +104,49 @@ pub fn texture_model(vertices: &Vec<Vertex>, indices: &Vec<u32>, attribs: &Vec<A
     rgl::Model::create(vao, vbo, ebo, indices.len() as i32)
 }
 
-pub fn cube_block_model() -> BlockModel {
-    let cube_vertices: [ShapeVertex; 4*6] = [
-        ShapeVertex::new(0.5, -0.5, -0.5, 1.0, 0.0, 0.0, 1.0, 1.0),
-        ShapeVertex::new(0.5,  0.5, -0.5, 1.0, 0.0, 0.0, 0.0, 1.0),
-        ShapeVertex::new(0.5,  0.5,  0.5, 1.0, 0.0, 0.0, 0.0, 0.0),
-        ShapeVertex::new(0.5, -0.5,  0.5, 1.0, 0.0, 0.0, 1.0, 0.0), //+X
-
-        ShapeVertex::new(-0.5, -0.5, -0.5, -1.0, 0.0, 0.0, 0.0, 1.0),
-        ShapeVertex::new(-0.5,  0.5, -0.5, -1.0, 0.0, 0.0, 1.0, 1.0),
-        ShapeVertex::new(-0.5,  0.5,  0.5, -1.0, 0.0, 0.0, 1.0, 0.0),
-        ShapeVertex::new(-0.5, -0.5,  0.5, -1.0, 0.0, 0.0, 0.0, 0.0), //-X
-
-        ShapeVertex::new(-0.5,  0.5, -0.5, 0.0, 1.0, 0.0, 0.0, 1.0),
-        ShapeVertex::new( 0.5,  0.5, -0.5, 0.0, 1.0, 0.0, 1.0, 1.0),
-        ShapeVertex::new( 0.5,  0.5,  0.5, 0.0, 1.0, 0.0, 1.0, 0.0),
-        ShapeVertex::new(-0.5,  0.5,  0.5, 0.0, 1.0, 0.0, 0.0, 0.0), //+Y
-
-        ShapeVertex::new(-0.5, -0.5, -0.5, 0.0, -1.0, 0.0, 0.0, 1.0),
-        ShapeVertex::new( 0.5, -0.5, -0.5, 0.0, -1.0, 0.0, 1.0, 1.0),
-        ShapeVertex::new( 0.5, -0.5,  0.5, 0.0, -1.0, 0.0, 1.0, 0.0),
-        ShapeVertex::new(-0.5, -0.5,  0.5, 0.0, -1.0, 0.0, 0.0, 0.0), //-Y
-
-        ShapeVertex::new(-0.5, -0.5,  0.5, 0.0, 0.0, 1.0, 0.0, 1.0),
-        ShapeVertex::new( 0.5, -0.5,  0.5, 0.0, 0.0, 1.0, 1.0, 1.0),
-        ShapeVertex::new( 0.5,  0.5,  0.5, 0.0, 0.0, 1.0, 1.0, 0.0),
-        ShapeVertex::new(-0.5,  0.5,  0.5, 0.0, 0.0, 1.0, 0.0, 0.0), //+Z
-
-        ShapeVertex::new(-0.5, -0.5, -0.5, 0.0, 0.0, -1.0, 0.0, 1.0),
-        ShapeVertex::new( 0.5, -0.5, -0.5, 0.0, 0.0, -1.0, 1.0, 1.0),
-        ShapeVertex::new( 0.5,  0.5, -0.5, 0.0, 0.0, -1.0, 1.0, 0.0),
-        ShapeVertex::new(-0.5,  0.5, -0.5, 0.0, 0.0, -1.0, 0.0, 0.0), //Квадрат -Z
-    ];
-    let cube_indices: [(u32, u32, u32); 2 * 6] = [
-        (2,  3,  0),  (0,  1,  2),
-        (2,  1,  0),  (0,  3,  2),
-        (2,  1,  0),  (0,  3,  2),
-        (2,  3,  0),  (0,  1,  2),
-        (2,  3,  0),  (0,  1,  2),
-        (2,  1,  0),  (0,  3,  2),
-    ];
+/** Углы стороны куба (знаки координат) и ее треугольники */
+type CubeFace = ([(i8, i8, i8); 4], [(u32, u32, u32); 2]);
+/** Стороны куба в порядке BlockFace */
+const CUBE_FACES: [CubeFace; 6] = [
+    ([( 1, -1, -1), ( 1,  1, -1), ( 1,  1,  1), ( 1, -1,  1)], [(2, 3, 0), (0, 1, 2)]), //+X
+    ([(-1, -1, -1), (-1,  1, -1), (-1,  1,  1), (-1, -1,  1)], [(2, 1, 0), (0, 3, 2)]), //-X
+    ([(-1,  1, -1), ( 1,  1, -1), ( 1,  1,  1), (-1,  1,  1)], [(2, 1, 0), (0, 3, 2)]), //+Y
+    ([(-1, -1, -1), ( 1, -1, -1), ( 1, -1,  1), (-1, -1,  1)], [(2, 3, 0), (0, 1, 2)]), //-Y
+    ([(-1, -1,  1), ( 1, -1,  1), ( 1,  1,  1), (-1,  1,  1)], [(2, 3, 0), (0, 1, 2)]), //+Z
+    ([(-1, -1, -1), ( 1, -1, -1), ( 1,  1, -1), (-1,  1, -1)], [(2, 1, 0), (0, 3, 2)]), //-Z
+];
 
+/** Сторона face параллелепипеда min..max (координаты относительно центра блока).
+    Текстура проецируется так же, как на сторону куба, поэтому части сторон разных фигур стыкуются без швов */
+fn box_face(min: Vec3, max: Vec3, face: BlockFace) -> BMShape {
+    let (corners, indices) = CUBE_FACES[usize::from(face)];
+    let (nx, ny, nz) = face.offset();
+    let pick = |sign: i8, min: f32, max: f32| if sign > 0 { max } else { min };
+
+    let mut shape = BMShape::new();
+    for (sx, sy, sz) in corners {
+        let (x, y, z) = (pick(sx, min.x(), max.x()), pick(sy, min.y(), max.y()), pick(sz, min.z(), max.z()));
+        let (u, v) = match face {
+            BlockFace::PX => (0.5 - y, 0.5 - z),
+            BlockFace::NX => (y + 0.5, 0.5 - z),
+            BlockFace::PY | BlockFace::NY => (x + 0.5, 0.5 - z),
+            BlockFace::PZ | BlockFace::NZ => (x + 0.5, 0.5 - y),
+        };
+        shape.vertex(ShapeVertex::new(x, y, z, nx as f32, ny as f32, nz as f32, u, v));
+    }
+    shape.index(indices[0]).index(indices[1]);
+    shape
+}
+/** Стороны faces параллелепипеда min..max (см. box_face) */
+fn box_shapes(min: Vec3, max: Vec3, faces: &[BlockFace]) -> Vec<BMShape> {
+    faces.iter().map(|face| box_face(min, max, *face)).collect()
+}
+
+pub fn cube_block_model() -> BlockModel {
+    let (min, max) = (Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5));
     let mut cube = BlockModel::new("cube".into());
-    cube.solid();
-    for face in 0..6 {
-        let mut shape = BMShape::new();
-        for i in 0..4 {
-            let v = cube_vertices[face * 4 + i];
-            shape.vertex(v);
-        }
-        shape.index(cube_indices[face * 2 + 0])
-            .index(cube_indices[face * 2 + 1])
-            .set_face(face.into());
-        cube.add(shape);
-        if face < 5 { cube.new_group(); }
+    for face in BlockFace::ALL {
+        cube.new_group().add(box_face(min, max, face));
     }
     cube
 }
@@ -177,7 +167,6 @@ pub fn cylinder_block_model() -> BlockModel {
 
     //Для боковых граней нормаль на вершине совпадает с позицией вершины по xy
     let indices: Vec<(u32, u32, u32)> = vec![(0, 1, 3), (1, 4, 3), (1, 2, 4), (2, 5, 4)];
-    let overlaps: Vec<u8> = vec![0b00110110, 0b00110101, 0b00111001, 0b00111010];
     for side in 0..4 {
         let mut side_shape: BMShape = BMShape::new();
         for z_side in 0..2 {
@@ -191,13 +180,11 @@ pub fn cylinder_block_model() -> BlockModel {
             }
         }
         side_shape.indices(&indices);
-        side_shape.overlap_state = DenseBools(overlaps[side]);
         model.add(side_shape);
     }
 
     model.new_group();
     let mut shape_pz = BMShape::new();
-    shape_pz.set_face(BlockFace::PZ);
     for i in 8..16 {
         let pos = positions[i];
         shape_pz.vertex(ShapeVertex::new(
@@ -212,7 +199,6 @@ pub fn cylinder_block_model() -> BlockModel {
 
     model.new_group();
     let mut shape_nz = BMShape::new();
-    shape_nz.set_face(BlockFace::NZ);
     for i in 0..8 {
         let pos = positions[i];
         shape_nz.vertex(ShapeVertex::new(
@@ -228,6 +214,102 @@ pub fn cylinder_block_model() -> BlockModel {
     model
 }
 
+/** Плита в половину блока: нижняя или верхняя (top). Группы текстур: бока, верх, низ */
+pub fn slab_block_model(top: bool) -> BlockModel {
+    let (z0, z1) = if top { (0.0, 0.5) } else { (-0.5, 0.0) };
+    let (min, max) = (Vec3::new(-0.5, -0.5, z0), Vec3::new(0.5, 0.5, z1));
+
+    let mut model = BlockModel::new(if top { "slab_top" } else { "slab_bottom" }.into());
+    model.add_shapes(box_shapes(min, max, &[BlockFace::PX, BlockFace::NX, BlockFace::PY, BlockFace::NY]));
+    model.new_group().add(box_face(min, max, BlockFace::PZ));
+    model.new_group().add(box_face(min, max, BlockFace::NZ));
+    model
+}
+
+/** Ступеньки, поднимающиеся в сторону +X (направление задается свойством facing). Группы текстур: бока, верх, низ */
+pub fn stairs_block_model() -> BlockModel {
+    use BlockFace::*;
+    //Нижняя ступень и высокая половина блока
+    let (lower_min, lower_max) = (Vec3::new(-0.5, -0.5, -0.5), Vec3::new(0.0, 0.5, 0.0));
+    let (upper_min, upper_max) = (Vec3::new(0.0, -0.5, -0.5), Vec3::new(0.5, 0.5, 0.5));
+
+    let mut model = BlockModel::new("stairs".into());
+    model.add_shapes(box_shapes(lower_min, lower_max, &[NX, PY, NY]))
+        .add_shapes(box_shapes(upper_min, upper_max, &[PX, PY, NY]))
+        .add(box_face(Vec3::new(0.0, -0.5, 0.0), upper_max, NX)); //Подступенок
+    model.new_group()
+        .add(box_face(lower_min, lower_max, PZ))
+        .add(box_face(upper_min, upper_max, PZ));
+    model.new_group()
+        .add(box_face(lower_min, lower_max, NZ))
+        .add(box_face(upper_min, upper_max, NZ));
+    model
+}
+
+/** Столб в центре блока с перекладинами к соседям, с которыми блок соединяется (см. Game::block_connections).
+    post - половина толщины столба, bars - перекладины: половина толщины, низ и верх */
+fn post_block_model(name: &str, post: f32, bars: &[(f32, f32, f32)]) -> BlockModel {
+    use BlockFace::*;
+    let mut model = BlockModel::new(name.into());
+    model.add_shapes(box_shapes(Vec3::new(-post, -post, -0.5), Vec3::new(post, post, 0.5), &BlockFace::ALL));
+    for face in [PX, NX, PY, NY] {
+        for (w, z0, z1) in bars.iter().copied() {
+            let (min, max) = match face {
+                PX => (Vec3::new(post, -w, z0), Vec3::new(0.5, w, z1)),
+                NX => (Vec3::new(-0.5, -w, z0), Vec3::new(-post, w, z1)),
+                PY => (Vec3::new(-w, post, z0), Vec3::new(w, 0.5, z1)),
+                _  => (Vec3::new(-w, -0.5, z0), Vec3::new(w, -post, z1)),
+            };
+            //Торцов у перекладины нет: один упирается в столб, другой - в соседа
+            let sides: Vec<BlockFace> = BlockFace::ALL.iter().copied().filter(|f| *f != face && *f != face.opposite()).collect();
+            for mut shape in box_shapes(min, max, &sides) {
+                shape.connected(face);
+                model.add(shape);
+            }
+        }
+    }
+    model
+}
+/** Забор: тонкий столб и две перекладины к соседям */
+pub fn fence_block_model() -> BlockModel {
+    post_block_model("fence", 0.125, &[(0.0625, -0.125, 0.0625), (0.0625, 0.25, 0.4375)])
+}
+/** Стена: толстый столб и низкая стенка к соседям */
+pub fn wall_block_model() -> BlockModel {
+    post_block_model("wall", 0.25, &[(0.1875, -0.5, 0.3125)])
+}
+/** Тонкая панель (стекло) во всю высоту блока, соединяющаяся с соседями */
+pub fn pane_block_model() -> BlockModel {
+    post_block_model("pane", 0.0625, &[(0.0625, -0.5, 0.5)])
+}
+
+/** Две плоскости накрест по диагоналям блока (трава, цветы). Видны с обеих сторон, сквозь них можно проходить */
+pub fn cross_block_model() -> BlockModel {
+    let mut model = BlockModel::new("cross".into());
+    model.no_collision();
+    let d = std::f32::consts::FRAC_1_SQRT_2;
+    for (a, b) in [((-0.5, -0.5), (0.5, 0.5)), ((-0.5, 0.5), (0.5, -0.5))] {
+        //Нормаль лицевой стороны - влево от направления a -> b
+        let normal = ((a.1 - b.1) * d, (b.0 - a.0) * d);
+        for side in [1.0, -1.0] {
+            let mut shape = BMShape::new();
+            let (nx, ny) = (normal.0 * side, normal.1 * side);
+            shape.vertex(ShapeVertex::new(a.0, a.1, -0.5, nx, ny, 0.0, 0.0, 1.0))
+                .vertex(ShapeVertex::new(b.0, b.1, -0.5, nx, ny, 0.0, 1.0, 1.0))
+                .vertex(ShapeVertex::new(b.0, b.1,  0.5, nx, ny, 0.0, 1.0, 0.0))
+                .vertex(ShapeVertex::new(a.0, a.1,  0.5, nx, ny, 0.0, 0.0, 0.0));
+            //Обход против часовой стрелки, если смотреть навстречу нормали
+            if side > 0.0 {
+                shape.index((2, 1, 0)).index((0, 3, 2));
+            } else {
+                shape.index((0, 1, 2)).index((2, 3, 0));
+            }
+            model.add(shape);
+        }
+    }
+    model
+}
+
 pub fn get_first_word(path: PathBuf) -> String {
     let words: Vec<&str> = path.to_str().unwrap().split("\\").collect();
     let parts: Vec<&str> = words[0].split(".").collect();
@@ -0,0 +1,163 @@
+use std::collections::{HashSet, VecDeque};
+use crate::game::{is_opaque_block, BlockData, BlockFace, BlockModel, Chunk, ChunkPos, Game, CHUNK_SIZE, CHUNK_VOLUME};
+
+/** Какие стороны чанка видны друг из друга через несплошные блоки внутри чанка.
+    Бит a * 6 + b означает, что из стороны a видна сторона b (матрица симметрична) */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChunkVisibility(u64);
+impl ChunkVisibility {
+    /** Все стороны видны друг из друга (например, пустой чанк) */
+    pub fn all() -> Self { Self((1 << 36) - 1) }
+    /** Ни одна сторона не видна из другой (сплошной чанк) */
+    pub fn none() -> Self { Self(0) }
+
+    pub fn connects(&self, a: BlockFace, b: BlockFace) -> bool {
+        let (a, b): (usize, usize) = (a.into(), b.into());
+        self.0 & (1 << (a * 6 + b)) != 0
+    }
+    fn connect(&mut self, a: usize, b: usize) {
+        self.0 |= (1 << (a * 6 + b)) | (1 << (b * 6 + a));
+    }
+}
+
+impl Chunk {
+    /** Связность сторон чанка: заливка областей из несплошных блоков (хотя бы одна сторона модели не сплошная или блок с прозрачностью),
+        все стороны чанка, которых касается одна область, видны друг из друга */
+    pub fn visibility(&self, blocks_data: &[BlockData], models_data: &[BlockModel]) -> ChunkVisibility {
+        if self.blocks_count() == 0 { return ChunkVisibility::all(); }
+        let chs = CHUNK_SIZE as i32;
+        let is_solid = |id: usize| is_opaque_block(blocks_data, models_data, self.blocks.get(id), self.states.get(id));
+
+        let mut visited = vec![false; CHUNK_VOLUME];
+        let mut result = ChunkVisibility::none();
+        let mut queue = VecDeque::new();
+        for start in 0..CHUNK_VOLUME {
+            if visited[start] || is_solid(start) { continue; }
+            visited[start] = true;
+            queue.push_back(start);
+            //Стороны чанка, которых касается область (биты по BlockFace)
+            let mut touched = 0u8;
+            while let Some(id) = queue.pop_front() {
+                let (x, y, z) = ((id % CHUNK_SIZE) as i32, ((id / CHUNK_SIZE) % CHUNK_SIZE) as i32, (id / (CHUNK_SIZE * CHUNK_SIZE)) as i32);
+                for face in BlockFace::ALL {
+                    let (dx, dy, dz) = face.offset();
+                    let (nx, ny, nz) = (x + dx, y + dy, z + dz);
+                    if nx < 0 || ny < 0 || nz < 0 || nx >= chs || ny >= chs || nz >= chs {
+                        touched |= 1 << usize::from(face);
+                        continue;
+                    }
+                    let neighbour = Chunk::pos_id(nx as usize, ny as usize, nz as usize);
+                    if !visited[neighbour] && !is_solid(neighbour) {
+                        visited[neighbour] = true;
+                        queue.push_back(neighbour);
+                    }
+                }
+            }
+            for a in 0..6 {
+                for b in 0..6 {
+                    if touched & (1 << a) != 0 && touched & (1 << b) != 0 { result.connect(a, b); }
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Game {
+    /** Связность сторон чанка, рассчитанная при построении его модели. None, если модели еще не было */
+    pub fn chunk_visibility(&self, pos: ChunkPos) -> Option<ChunkVisibility> { self.visibility.get(&pos).copied() }
+
+    /** Чанки, которые могут быть видны из чанка камеры camera: поиск в ширину по соседним чанкам.
+        В чанк можно пройти, только если сторона, через которую в него вошли, видна из стороны выхода,
+        и путь не поворачивает назад к камере (не идет в направлении, противоположном уже пройденному).
+        Незагруженные чанки (кроме чанка камеры) и чанки, для которых include возвращает false, не проходятся.
+        Чанки без рассчитанной связности считаются пустыми */
+    pub fn visible_chunks<F>(&self, camera: ChunkPos, include: F) -> HashSet<ChunkPos>
+        where F: Fn(ChunkPos) -> bool
+    {
+        let mut visible = HashSet::from([camera]);
+        //Чанк, сторона, через которую в него вошли, и пройденные направления (биты по BlockFace)
+        let mut queue: VecDeque<(ChunkPos, Option<BlockFace>, u8)> = VecDeque::from([(camera, None, 0)]);
+        while let Some((pos, entered, directions)) = queue.pop_front() {
+            let visibility = self.chunk_visibility(pos).unwrap_or(ChunkVisibility::all());
+            for face in BlockFace::ALL {
+                if directions & (1 << usize::from(face.opposite())) != 0 { continue; }
+                if let Some(entered) = entered {
+                    if !visibility.connects(entered, face) { continue; }
+                }
+                let (dx, dy, dz) = face.offset();
+                let next = (pos.0 + dx, pos.1 + dy, pos.2 + dz);
+                if visible.contains(&next) || !self.chunks.contains(next) || !include(next) { continue; }
+                visible.insert(next);
+                queue.push_back((next, Some(face.opposite()), directions | (1 << usize::from(face))));
+            }
+        }
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::{test_game, BlockId};
+    use super::*;
+
+    const STONE: BlockId = 3;
+
+    fn solid_chunk(game: &Game, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::empty(pos.0, pos.1, pos.2);
+        let solid_faces = game.block_solid_faces(STONE, 0);
+        for x in 0..CHUNK_SIZE as i32 {
+            for y in 0..CHUNK_SIZE as i32 {
+                for z in 0..CHUNK_SIZE as i32 {
+                    chunk.set_block(STONE, x, y, z, solid_faces);
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn empty_and_solid_chunks() {
+        let game = test_game();
+        assert_eq!(Chunk::empty(0, 0, 0).visibility(&game.blocks, &game.models), ChunkVisibility::all());
+        assert_eq!(solid_chunk(&game, (0, 0, 0)).visibility(&game.blocks, &game.models), ChunkVisibility::none());
+    }
+
+    #[test]
+    fn tunnel_connects_two_faces() {
+        let game = test_game();
+        //Прямой туннель вдоль x через сплошной чанк
+        let mut chunk = solid_chunk(&game, (0, 0, 0));
+        for x in 0..CHUNK_SIZE as i32 {
+            chunk.set_block(0, x, 5, 5, game.block_solid_faces(0, 0));
+        }
+        let visibility = chunk.visibility(&game.blocks, &game.models);
+        assert!(visibility.connects(BlockFace::PX, BlockFace::NX));
+        assert!(visibility.connects(BlockFace::NX, BlockFace::PX));
+        for (a, b) in [(BlockFace::PX, BlockFace::PY), (BlockFace::NX, BlockFace::NZ), (BlockFace::PZ, BlockFace::NZ), (BlockFace::PY, BlockFace::NY)] {
+            assert!(!visibility.connects(a, b), "{:?} - {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn chunk_behind_solid_chunk_is_not_reached() {
+        //Ряд чанков вдоль x: камера в (0, 0, 0), за ней (1, 0, 0) и (2, 0, 0)
+        for middle_is_solid in [false, true] {
+            let mut game = test_game();
+            game.add_chunk(Chunk::empty(0, 0, 0));
+            game.add_chunk(if middle_is_solid { solid_chunk(&game, (1, 0, 0)) } else { Chunk::empty(1, 0, 0) });
+            game.add_chunk(Chunk::empty(2, 0, 0));
+            for pos in [(0, 0, 0), (1, 0, 0), (2, 0, 0)] {
+                let visibility = game.chunk(pos).unwrap().visibility(&game.blocks, &game.models);
+                game.visibility.insert(pos, visibility);
+            }
+
+            let visible = game.visible_chunks((0, 0, 0), |_| true);
+            //Сам сплошной чанк виден (его сторона обращена к камере), а то, что за ним, - нет
+            assert!(visible.contains(&(0, 0, 0)) && visible.contains(&(1, 0, 0)));
+            assert_eq!(visible.contains(&(2, 0, 0)), !middle_is_solid);
+            //Отброшенный фильтром чанк не проходится
+            assert!(!game.visible_chunks((0, 0, 0), |pos| pos != (1, 0, 0)).contains(&(2, 0, 0)));
+        }
+    }
+}
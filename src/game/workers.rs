@@ -0,0 +1,138 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::JoinHandle;
+use crate::game::{BlockData, BlockModel, Chunk, ChunkHalo, ChunkMeshes, ChunkPos, ChunkVisibility, TexData, WorldGenerator};
+
+/** Неизменяемые данные игры, нужные для построения моделей чанков в других потоках */
+pub struct MeshContext {
+    pub blocks: Vec<BlockData>,
+    pub models: Vec<BlockModel>,
+    pub materials: Vec<TexData>,
+}
+
+/** Задача для фоновых потоков */
+pub enum ChunkJob {
+    /** Сгенерировать чанк */
+    Generate(ChunkPos),
+    /** Построить модель копии чанка. version - версия чанка на момент копирования */
+    Mesh { chunk: Box<Chunk>, halo: Box<ChunkHalo>, version: u64 },
+}
+
+/** Результат фоновой задачи */
+pub enum ChunkJobResult {
+    /** Сгенерированный чанк со светом, рассчитанным без соседей (Chunk::light_local) */
+    Generated(Box<Chunk>),
+    Meshed { pos: ChunkPos, mesh: ChunkMeshes, visibility: ChunkVisibility, version: u64 },
+}
+
+/** Пул потоков, генерирующих и освещающих чанки и строящих их модели */
+pub struct ChunkWorkers {
+    jobs: Option<mpsc::Sender<ChunkJob>>,
+    results: mpsc::Receiver<ChunkJobResult>,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+impl ChunkWorkers {
+    pub fn new(threads: usize, context: Arc<MeshContext>, generator: Arc<dyn WorldGenerator>, seed: u64) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<ChunkJob>();
+        let (result_sender, results) = mpsc::channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let threads = (0..threads.max(1)).map(|i| {
+            let (job_receiver, result_sender) = (job_receiver.clone(), result_sender.clone());
+            let (context, generator, stop) = (context.clone(), generator.clone(), stop.clone());
+            std::thread::Builder::new()
+                .name(format!("chunk worker {}", i))
+                .spawn(move || {
+                    loop {
+                        //Блокировка держится, пока поток ждет задачу (recv блокирует), и снимается перед ее выполнением.
+                        //Так задачу ждет только один свободный поток, остальные ждут блокировку
+                        let job = match job_receiver.lock().unwrap().recv() {
+                            Ok(job) => job,
+                            Err(_) => break,
+                        };
+                        if stop.load(Ordering::Relaxed) { break; }
+                        let result = match job {
+                            ChunkJob::Generate(pos) => {
+                                let mut chunk = generator.generate(seed, pos);
+                                chunk.light_local(&context.blocks, &context.models);
+                                ChunkJobResult::Generated(Box::new(chunk))
+                            }
+                            ChunkJob::Mesh { chunk, halo, version } => ChunkJobResult::Meshed {
+                                pos: chunk.pos(),
+                                mesh: chunk.build_mesh(&context.blocks, &context.models, &context.materials, &halo),
+                                visibility: chunk.visibility(&context.blocks, &context.models),
+                                version,
+                            },
+                        };
+                        if result_sender.send(result).is_err() { break; }
+                    }
+                })
+                .expect("Failed to start chunk worker")
+        }).collect();
+
+        Self { jobs: Some(jobs), results, stop, threads }
+    }
+
+    /** Поставить задачу в очередь */
+    pub fn send(&self, job: ChunkJob) {
+        if let Some(jobs) = &self.jobs {
+            let _ = jobs.send(job);
+        }
+    }
+    /** Готовый результат, если он есть. Не ждет */
+    pub fn try_recv(&self) -> Option<ChunkJobResult> {
+        self.results.try_recv().ok()
+    }
+}
+impl Drop for ChunkWorkers {
+    /** Оставшиеся в очереди задачи отбрасываются, текущие дорабатываются */
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.jobs = None;
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::game::{test_game, GeneratorPreset};
+    use super::*;
+
+    #[test]
+    fn stale_mesh_is_dropped_and_chunk_requeued() {
+        let mut game = test_game();
+        let generator = GeneratorPreset::Classic.create(&game).unwrap();
+        game.start_workers(1, generator.into(), 0);
+        game.add_chunk(Chunk::empty(0, 0, 0));
+        game.set_block(3, 5, 5, 5);
+
+        //Модель отправлена строиться, результатов в этом же вызове еще нет
+        let budget = Duration::from_secs(1);
+        assert!(game.update_chunk_models(budget).is_empty());
+        let first = game.meshing[&(0, 0, 0)];
+        assert_eq!(first, game.chunk((0, 0, 0)).unwrap().version());
+        assert!(game.dirty.is_empty());
+
+        //Чанк меняется, пока строится модель
+        game.set_block(3, 6, 5, 5);
+        let second = game.chunk((0, 0, 0)).unwrap().version();
+        assert_ne!(first, second);
+
+        //Пока старая модель не готова, новая не отправляется. Готовая старая отбрасывается, а чанк отправляется снова
+        let start = Instant::now();
+        while game.meshing.get(&(0, 0, 0)) == Some(&first) {
+            assert!(start.elapsed() < Duration::from_secs(10), "модель не построена");
+            assert!(game.update_chunk_models(budget).is_empty());
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(game.meshing.get(&(0, 0, 0)), Some(&second));
+        assert!(game.chunk_model((0, 0, 0)).is_none());
+        assert!(game.chunk_visibility((0, 0, 0)).is_none());
+        assert!(game.dirty.is_empty());
+    }
+}
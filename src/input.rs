@@ -1,8 +1,16 @@
 use sdl2::event::{Event};
 use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::mouse::MouseButton;
+
+/** Клавиша клавиатуры или кнопка мыши */
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Button {
+    Key(Keycode),
+    Mouse(MouseButton),
+}
 
 pub struct Input {
-    data: Vec<(Keycode, KeyData, Vec<CheckEvent>)>,
+    data: Vec<(Button, KeyData, Vec<CheckEvent>)>,
     window_id: Option<u32>,
 }
 impl Input {
@@ -26,14 +34,27 @@ impl Input {
                     if id == window_id { self.key_up(timestamp, keycode, scancode, keymod, repeat); }
                 }
             },
+            sdl2::event::Event::MouseButtonDown {timestamp, window_id, mouse_btn, ..}
+                if self.window_id.is_none() || self.window_id == Some(window_id) => {
+                self.button_down(timestamp, Button::Mouse(mouse_btn));
+            },
+            sdl2::event::Event::MouseButtonUp {timestamp, window_id, mouse_btn, ..}
+                if self.window_id.is_none() || self.window_id == Some(window_id) => {
+                self.button_up(timestamp, Button::Mouse(mouse_btn));
+            },
             _ => {}
         }
 
     }
     fn key_down(&mut self, timestamp: u32, keycode: Option<Keycode>, _scancode: Option<Scancode>, _keymod: Mod, _repeat: bool) {
-        if let None = keycode { return; }
+        if let Some(keycode) = keycode { self.button_down(timestamp, Button::Key(keycode)); }
+    }
+    fn key_up(&mut self, timestamp: u32, keycode: Option<Keycode>, _scancode: Option<Scancode>, _keymod: Mod, _repeat: bool) {
+        if let Some(keycode) = keycode { self.button_up(timestamp, Button::Key(keycode)); }
+    }
+    fn button_down(&mut self, timestamp: u32, button: Button) {
         for i in 0..self.data.len() {
-            if self.data[i].0 == keycode.unwrap() {
+            if self.data[i].0 == button {
                 self.data[i].1.pressed = true;
                 self.data[i].1.last_pressed = timestamp;
                 return;
@@ -41,16 +62,15 @@ impl Input {
         }
         self.data.push(
             (
-                keycode.unwrap(),
+                button,
                 KeyData{ pressed:true, last_pressed: timestamp, last_released: 0 },
                 Vec::new()
             )
         );
     }
-    fn key_up(&mut self, timestamp: u32, keycode: Option<Keycode>, _scancode: Option<Scancode>, _keymod: Mod, _repeat: bool) {
-        if let None = keycode { return; }
+    fn button_up(&mut self, timestamp: u32, button: Button) {
         for i in 0..self.data.len() {
-            if self.data[i].0 == keycode.unwrap() {
+            if self.data[i].0 == button {
                 self.data[i].1.pressed = false;
                 self.data[i].1.last_released = timestamp;
                 return;
@@ -58,26 +78,26 @@ impl Input {
         }
         self.data.push(
             (
-                keycode.unwrap(),
+                button,
                 KeyData{ pressed:true, last_pressed: timestamp, last_released: 0 },
                 Vec::new(),
             )
         );
     }
 
-    pub fn key_pressed(&self, keycode: Keycode) -> bool {
+    pub fn button_pressed(&self, button: Button) -> bool {
         for i in 0..self.data.len() {
-            if self.data[i].0 == keycode { return self.data[i].1.pressed; }
+            if self.data[i].0 == button { return self.data[i].1.pressed; }
         }
         false
     }
     /** Позволяет реагировать на нажатие/отпускание кнопки через конструкцию
-        if input.on_pressed(LShift, N) {...}
+        if input.on_button_pressed(Button::Key(LShift), N) {...}
         Где вместо N нужно подставить уникальный айди чекера. Если несколько блоков if
         будут использовать один айди - на ивент среагирует первый выполнившийся if, остальные не среагируют. */
-    pub fn on_pressed(&mut self, keycode: Keycode, checker_id: usize) -> bool {
-        for (kc, state, checkers) in self.data.iter_mut() {
-            if *kc == keycode {
+    pub fn on_button_pressed(&mut self, button: Button, checker_id: usize) -> bool {
+        for (b, state, checkers) in self.data.iter_mut() {
+            if *b == button {
                 for i in 0..checkers.len() {
                     if checkers[i].id == checker_id { //Если такой чекер существует
                         let result = checkers[i].prev != state.pressed;
@@ -93,7 +113,7 @@ impl Input {
         //Если этой клавиши еще нет в базе
         self.data.push(
             (
-                keycode,
+                button,
                 KeyData{ pressed: false, last_pressed: 0, last_released: 0 },
                 vec![ CheckEvent{id: checker_id, prev: false} ],
                 )
@@ -102,12 +122,12 @@ impl Input {
     }
 
     /** Позволяет реагировать на нажатие/отпускание кнопки через конструкцию
-       if input.on_released(LShift, N) {...}
+       if input.on_button_released(Button::Key(LShift), N) {...}
        Где вместо N нужно подставить уникальный айди чекера. Если несколько блоков if
        будут использовать один айди - на ивент среагирует первый выполнившийся if, остальные не среагируют. */
-    pub fn on_released(&mut self, keycode: Keycode, checker_id: usize) -> bool {
-        for (kc, state, checkers) in self.data.iter_mut() {
-            if *kc == keycode {
+    pub fn on_button_released(&mut self, button: Button, checker_id: usize) -> bool {
+        for (b, state, checkers) in self.data.iter_mut() {
+            if *b == button {
                 for i in 0..checkers.len() {
                     if checkers[i].id == checker_id { //Если такой чекер существует
                         let result = checkers[i].prev != state.pressed;
@@ -123,7 +143,7 @@ impl Input {
         //Если этой клавиши еще нет в базе
         self.data.push(
             (
-                keycode,
+                button,
                 KeyData{ pressed: false, last_pressed: 0, last_released: 0 },
                 vec![ CheckEvent{id: checker_id, prev: false} ],
             )
@@ -131,6 +151,18 @@ impl Input {
         false
     }
 
+    pub fn key_pressed(&self, keycode: Keycode) -> bool { self.button_pressed(Button::Key(keycode)) }
+    /** Нажатие клавиши, см. on_button_pressed */
+    pub fn on_pressed(&mut self, keycode: Keycode, checker_id: usize) -> bool { self.on_button_pressed(Button::Key(keycode), checker_id) }
+    /** Отпускание клавиши, см. on_button_released */
+    pub fn on_released(&mut self, keycode: Keycode, checker_id: usize) -> bool { self.on_button_released(Button::Key(keycode), checker_id) }
+
+    pub fn mouse_pressed(&self, button: MouseButton) -> bool { self.button_pressed(Button::Mouse(button)) }
+    /** Нажатие кнопки мыши, см. on_button_pressed */
+    pub fn on_mouse_pressed(&mut self, button: MouseButton, checker_id: usize) -> bool { self.on_button_pressed(Button::Mouse(button), checker_id) }
+    /** Отпускание кнопки мыши, см. on_button_released */
+    pub fn on_mouse_released(&mut self, button: MouseButton, checker_id: usize) -> bool { self.on_button_released(Button::Mouse(button), checker_id) }
+
 }
 
 #[derive(Copy, Clone)]
@@ -4,16 +4,20 @@ extern crate sdl2;
 extern crate gl;
 extern crate core;
 
+use std::cell::RefCell;
+use std::collections::HashSet;
 use std::f32::consts::PI;
 use std::path::Path;
+use std::sync::Arc;
 use std::time::Duration;
 use image::DynamicImage;
 use sdl2::event::{Event, WindowEvent};
+use sdl2::mouse::MouseButton;
 use sdl2::video::{GLContext, SwapInterval, Window};
 use sdl2::VideoSubsystem;
 use crate::game::DenseBools;
 use crate::input::Input;
-use crate::mat::Mat4;
+use crate::mat::{Frustum, Mat4, Vec3};
 use crate::rgl::{Model, Program};
 
 pub mod rgl;
@@ -41,17 +45,26 @@ fn main() {
           "u_projview", "u_model", "u_light_projview",
           "u_materials", "u_texture_atlas",
           "u_atlas_size", "u_texture_size",
-          "u_light_direction", "u_camera_pos",
+          "u_light_direction", "u_camera_pos", "u_alpha_test",
       ]).unwrap();
 
     let lighting_pass = Program::from_res(&mut res, "shaders/deferred_rendering/lighting_pass",
       vec![
           "g_position", "g_normal", "g_color", "g_light",
+          "u_light_direction", "u_camera_pos", "u_projview",
+      ]).unwrap();
+
+    //Полупрозрачные блоки освещаются сразу при отрисовке, поверх результата прохода освещения
+    let translucent_pass = Program::from_res(&mut res, "shaders/deferred_rendering/translucent",
+      vec![
+          "u_projview", "u_model",
+          "u_materials", "u_texture_atlas",
+          "u_atlas_size", "u_texture_size",
           "u_light_direction", "u_camera_pos",
       ]).unwrap();
 
     let mut plr: game::Player = game::Player::new();
-    let game = game::Game::new(&res);
+    let mut game = game::Game::new(&res).expect("Failed to load blocks");
 
     geometry_pass.set_used();
     game.atlas().load_materials_to_shader(&geometry_pass, "u_materials");
@@ -64,12 +77,35 @@ fn main() {
     let _ = load_to_gpu_with_mipmaps(0, game.atlas().image(), 4, (15, 15));
     geometry_pass.uniform1i(4, 0);
 
-    let max_dist = 12;
-    let (chunks, blocks) = tmp_create_models(max_dist, &game);
-    let mut chunk_model_ids: Vec<usize> = Vec::with_capacity(chunks.len());
-    let mut block_model_ids: Vec<usize> = Vec::with_capacity(blocks.len());
+    translucent_pass.set_used();
+    game.atlas().load_materials_to_shader(&translucent_pass, "u_materials");
+    translucent_pass.uniform2f(4, game.atlas().width() as f32, game.atlas().height() as f32);
+    translucent_pass.uniform2f(5, game.atlas().tex_width() as f32, game.atlas().tex_height() as f32);
+    translucent_pass.uniform1i(3, 0);
+
+    let save = game::WorldSave::new(std::path::PathBuf::from("saves/world"));
+    let mut streamer = game::ChunkStreamer::new(save.clone(), 6);
+    let seed = 1u64;
+    let generator: Arc<dyn game::WorldGenerator> = game::GeneratorPreset::Noise.create(&game).expect("Generator blocks are missing").into();
+    //Один поток остается главному циклу
+    let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(2).saturating_sub(1);
+    game.start_workers(threads, generator.clone(), seed);
+    let blocks = tmp_create_models(&game);
+    //Чанк появления нужен сразу, остальные догрузятся в фоне
+    if !streamer.load_saved(&mut game, (0, 0, 0)).expect("Failed to load spawn chunk") {
+        game.add_chunk(generator.generate(seed, (0, 0, 0)));
+    }
+    //Появление на поверхности
+    plr.x = 16.0;
+    plr.y = 16.0;
+    plr.z = generator.surface_height(seed, 16, 16) as f64 + 0.5 + game::PLAYER_EYE_HEIGHT;
+    while game.collision_box(16, 16, (plr.z - game::PLAYER_EYE_HEIGHT + 0.5).floor() as i32).is_some() ||
+          game.collision_box(16, 16, (plr.z - game::PLAYER_EYE_HEIGHT + 1.5).floor() as i32).is_some() {
+        plr.z += 1.0;
+    }
 
-    let mut render_dist = 6usize;
+    //Блок, который ставится правой кнопкой мыши
+    let mut selected_block: game::BlockId = game.get_block_id("stone".into()).unwrap_or(1) as game::BlockId;
 
 
     ////////
@@ -86,27 +122,6 @@ fn main() {
     shadow_program.uniform_mat4(0, &light_proj_mat);*/
 
 
-    let mut models_list = ModelList::new();
-    for (_, model) in chunks.iter() { chunk_model_ids.push(models_list.add_model(model)); }
-    for model in blocks.iter() { block_model_ids.push(models_list.add_model(model)); }
-
-    {
-        for cx in 0..max_dist {
-            for cy in 0..max_dist {
-                let chunk = &chunks[(cy * max_dist + cx) as usize].0;
-                let id = chunk_model_ids[(cy * max_dist + cx) as usize];
-                let object = mat::Mat4::object_mat(32.0 * (chunk.x as f32), 3.0 + 32.0 * (chunk.y as f32), 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0);
-                models_list.place_object(id, object);
-            }
-        }
-        let step = 3.0_f32.sqrt();
-        for (i, _) in blocks.iter().enumerate() {
-            let id = block_model_ids[i];
-            let object = mat::Mat4::object_mat(i as f32 * step, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0);
-            models_list.place_object(id, object);
-        }
-    }
-
     let mut prev_frame: f64 = current_time();
 
     ////////
@@ -122,6 +137,8 @@ fn main() {
         //После - рисуем следующий и ждем
         let frame_start = current_time();
 
+        //Клик, которым захватывается курсор, не должен ломать блок
+        let cursor_was_captured = window_data.is_cursor_captured();
         // Обработка ввода
         for event in event_pump.poll_iter() {
             input.event(event.clone());
@@ -131,22 +148,110 @@ fn main() {
                 sdl2::event::Event::MouseMotion {xrel, yrel, ..} => {
                     if window_data.is_cursor_captured() { plr.rotate_by_mouse(xrel, -yrel, 0.004); }
                 }
+                //Колесо мыши перебирает все блоки, кроме воздуха
+                sdl2::event::Event::MouseWheel {y, ..} if y != 0 && window_data.is_cursor_captured() => {
+                    let count = game.blocks().len() as i32 - 1;
+                    let next = (selected_block as i32 - 1 - y.signum()).rem_euclid(count) + 1;
+                    selected_block = next as game::BlockId;
+                }
                 _ => {}
             }
         }
         window_data.handle_input(&mut input);
-        plr.move_by_input(&input, frame_start - prev_frame);
-        if input.on_pressed(sdl2::keyboard::Keycode::Up, 1) && render_dist < max_dist as usize && window_data.is_cursor_captured() { render_dist += 1; }
-        if input.on_pressed(sdl2::keyboard::Keycode::Down, 2) && window_data.is_cursor_captured() && render_dist > 0 { render_dist -= 1; }
+        if input.on_pressed(sdl2::keyboard::Keycode::F, 4) && window_data.is_cursor_captured() { plr.fly = !plr.fly; }
+        //Слишком длинный кадр (например, при загрузке) не должен проносить игрока сквозь стены
+        plr.move_by_input(&input, (frame_start - prev_frame).min(0.1), &game);
+        if input.on_pressed(sdl2::keyboard::Keycode::Up, 1) && streamer.render_dist() < MAX_RENDER_DIST && window_data.is_cursor_captured() {
+            streamer.set_render_dist(streamer.render_dist() + 1);
+        }
+        if input.on_pressed(sdl2::keyboard::Keycode::Down, 2) && window_data.is_cursor_captured() {
+            streamer.set_render_dist(streamer.render_dist() - 1);
+        }
 
+        //Выбор блока клавишами (BLOCK_KEYS)
+        for (i, key) in BLOCK_KEYS.iter().enumerate() {
+            if input.on_pressed(*key, 3) && i + 1 < game.blocks().len() { selected_block = (i + 1) as game::BlockId; }
+        }
 
-        //Включение моделей в рендер
-        for cx in 0..render_dist {
-            for cy in 0..render_dist {
-                models_list.set_renderable(chunk_model_ids[cy * (max_dist as usize) + cx]);
+        //Ломание и установка блоков
+        let eye = mat::Vec3::new(plr.x as f32, plr.y as f32, plr.z as f32);
+        let target = game.raycast(eye, plr.look_direction(), 8.0);
+        let break_clicked = input.on_mouse_pressed(MouseButton::Left, 0);
+        let place_clicked = input.on_mouse_pressed(MouseButton::Right, 0);
+        if let (Some(hit), true) = (target, cursor_was_captured && window_data.is_cursor_captured()) {
+            if break_clicked {
+                game.set_block(0, hit.pos.0, hit.pos.1, hit.pos.2);
+            } else if place_clicked {
+                let (x, y, z) = hit.adjacent;
+                //Нельзя поставить блок туда, где стоит сам игрок
+                let inside_player = !plr.fly && game::Aabb::new([-0.5; 3], [0.5; 3])
+                    .offset(x as f64, y as f64, z as f64).intersects(&plr.aabb());
+                if let (Some((0, _)), false) = (game.block_at(x, y, z), inside_player) {
+                    //У плит половина выбирается по точке попадания: верхняя, если она выше середины новой клетки
+                    let half = if hit.point.z() > z as f32 { "top" } else { "bottom" };
+                    let state = game.blocks()[selected_block as usize].state(&[("half", half)]).unwrap_or(0);
+                    game.set_block_state(selected_block, state, x, y, z);
+                }
+            }
+        }
+        //Подгрузка чанков вокруг игрока и выгрузка далеких
+        streamer.update(&mut game, (plr.x, plr.y, plr.z), plr.look_direction()).expect("Failed to stream chunks");
+        //Перестраиваются только чанки, которые изменились. Модели строятся в фоне, здесь только загрузка
+        game.update_chunk_models(CHUNK_UPDATE_BUDGET);
+
+        let view_mat = mat::Mat4::cam_mat(plr.ang_vert as f32, plr.ang_horz as f32, plr.x as f32, plr.y as f32, plr.z as f32);
+        let proj_mat = mat::Mat4::perspective_mat(std::f32::consts::PI / 2.0, (window_data.width() as f32) / (window_data.height() as f32), 0.05, 1024.0);
+        let u_projview = proj_mat * view_mat;
+        let frustum = Frustum::from_projview(&u_projview);
+
+        //Включение моделей в рендер: только чанки, которые могут быть видны из чанка камеры.
+        //Чанки вне пирамиды видимости отсекаются уже при обходе (и дальше через них обход не идет), здесь они и считаются
+        let center = game::chunk_pos_at((plr.x, plr.y, plr.z));
+        let frustum_culled = RefCell::new(HashSet::new());
+        let visible_chunks = game.visible_chunks(center, |pos| {
+            if !streamer.is_in_range(center, pos) { return false; }
+            let min = Vec3::new(32.0 * pos.0 as f32 - 0.5, 32.0 * pos.1 as f32 - 0.5, 32.0 * pos.2 as f32 - 0.5);
+            let inside = frustum.intersects_box(min, min + 32.0);
+            if !inside { frustum_culled.borrow_mut().insert(pos); }
+            inside
+        });
+        let frustum_culled = frustum_culled.into_inner().len();
+        //Полупрозрачные чанки рисуются от дальних к ближним, треугольники внутри них - тоже
+        let chunk_origin = |pos: game::ChunkPos| Vec3::new(32.0 * pos.0 as f32, 32.0 * pos.1 as f32, 32.0 * pos.2 as f32);
+        let mut translucent_chunks: Vec<(f32, game::ChunkPos)> = visible_chunks.iter().copied()
+            .filter(|pos| game.chunk_model(*pos).is_some_and(|m| m.translucent.is_some()))
+            .map(|pos| {
+                let d = chunk_origin(pos) + 15.5 - eye;
+                (d * d, pos)
+            })
+            .collect();
+        translucent_chunks.sort_by(|a, b| b.0.total_cmp(&a.0));
+        for (_, pos) in translucent_chunks.iter() {
+            if let Some(translucent) = game.chunk_model_mut(*pos).and_then(|m| m.translucent.as_mut()) {
+                translucent.sort(eye - chunk_origin(*pos));
             }
         }
-        for (i, _) in blocks.iter().enumerate() { models_list.set_renderable(block_model_ids[i]); }
+
+        let mut models_list = ModelList::new();
+        let mut cutout_list = ModelList::new();
+        let mut translucent_list = ModelList::new();
+        for pos in visible_chunks.iter().copied() {
+            if let Some(model) = game.chunk_model(pos) {
+                if let Some(opaque) = &model.opaque { models_list.add_chunk(opaque, chunk_origin(pos)); }
+                if let Some(cutout) = &model.cutout { cutout_list.add_chunk(cutout, chunk_origin(pos)); }
+            }
+        }
+        for (_, pos) in translucent_chunks.iter() {
+            if let Some(translucent) = game.chunk_model(*pos).and_then(|m| m.translucent.as_ref()) {
+                translucent_list.add_chunk(translucent.model(), chunk_origin(*pos));
+            }
+        }
+        let step = 3.0_f32.sqrt();
+        for (i, model) in blocks.iter().enumerate() {
+            let id = models_list.add_model(model);
+            let object = mat::Mat4::object_mat(i as f32 * step, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0);
+            models_list.place_object(id, object);
+        }
 
         //Немного освещения
         let light_direction = (-PI / 4.0, timed_ang(0.1));
@@ -161,24 +266,24 @@ fn main() {
             gl::Viewport(0, 0, depth_map_width, depth_map_height);
             gl::BindFramebuffer(gl::FRAMEBUFFER, depth_map_fbo);
             gl::Clear(gl::DEPTH_BUFFER_BIT);
-            models_list.render_all(&shadow_program, 2);*/
-
-            let view_mat = mat::Mat4::cam_mat(plr.ang_vert as f32, plr.ang_horz as f32, plr.x as f32, plr.y as f32, plr.z as f32);
-            let proj_mat = mat::Mat4::perspective_mat(std::f32::consts::PI / 2.0, (window_data.width() as f32) / (window_data.height() as f32), 0.05, 1024.0);
+            models_list.render_all(&shadow_program, 2, &Frustum::from_projview(&light_projview));*/
 
             //Geometry pass
             gl::BindFramebuffer(gl::FRAMEBUFFER, g_framebuffer);
             gl::Viewport(0, 0, 1366, 768);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
             geometry_pass.set_used();
-            let u_projview = proj_mat * view_mat;
             geometry_pass.uniform_mat4(0, &u_projview);
             geometry_pass.uniform_mat4(2, &light_projview);
 
             geometry_pass.uniform3f(7, light_vec.x, light_vec.y, light_vec.z);
             geometry_pass.uniform3f(8, plr.x as f32, plr.y as f32, plr.z as f32);
-            models_list.render_all(&geometry_pass, 1);
+            geometry_pass.uniform1i(9, 0);
+            models_list.render_all(&geometry_pass, 1, &frustum);
             models_list.finish_render();
+            geometry_pass.uniform1i(9, 1);
+            cutout_list.render_all(&geometry_pass, 1, &frustum);
+            cutout_list.finish_render();
 
             //Lighting pass
             gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
@@ -205,14 +310,41 @@ fn main() {
 
             lighting_pass.uniform3f(4, light_vec.x, light_vec.y, light_vec.z);
             lighting_pass.uniform3f(5, plr.x as f32, plr.y as f32, plr.z as f32);
+            lighting_pass.uniform_mat4(6, &u_projview);
 
+            //Проход освещения восстанавливает глубину сцены, чтобы полупрозрачные блоки прятались за непрозрачными
+            gl::DepthFunc(gl::ALWAYS);
             fullscreen_square.render();
+            gl::DepthFunc(gl::LESS);
+
+            //Translucent pass
+            gl::ActiveTexture(gl::TEXTURE0);
+            translucent_pass.set_used();
+            translucent_pass.uniform_mat4(0, &u_projview);
+            translucent_pass.uniform3f(6, light_vec.x, light_vec.y, light_vec.z);
+            translucent_pass.uniform3f(7, plr.x as f32, plr.y as f32, plr.z as f32);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+            translucent_list.render_all(&translucent_pass, 1, &frustum);
+            translucent_list.finish_render();
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
         }
 
         prev_frame = frame_start;
         fps_counter.tick();
         //_ - результат этого действия не важен здесь
-        let _ = window_data.window.set_title(&format!("A lot of cubes | FPS: {:.2} | XYZ: {:.2}, {:.2}, {:.2} | VH: {:.2}, {:.2}", fps_counter.tps_corrected(), plr.x, plr.y, plr.z, plr.ang_vert, plr.ang_horz)[..]);
+        let target_name = match target {
+            Some(hit) => format!("{} {:?} {:?}", game.blocks()[hit.block as usize].name, hit.pos, hit.face),
+            None => "-".into(),
+        };
+        let _ = window_data.window.set_title(&format!("A lot of cubes | FPS: {:.2} | XYZ: {:.2}, {:.2}, {:.2} | VH: {:.2}, {:.2} | Target: {} | Block: {} | Chunks: {}/{} | Models: {} (culled {})",
+            fps_counter.tps_corrected(), plr.x, plr.y, plr.z, plr.ang_vert, plr.ang_horz,
+            target_name, game.blocks()[selected_block as usize].name,
+            visible_chunks.len(), game.chunks().len(),
+            models_list.visible_count() + cutout_list.visible_count() + translucent_list.visible_count(),
+            frustum_culled + models_list.culled_count() + cutout_list.culled_count() + translucent_list.culled_count())[..]);
 
         /* Спим до начала следующего кадра.
         Это нужно, поскольку VSync от SDL2 дико грузит процессор вхолостую,
@@ -224,36 +356,73 @@ fn main() {
             std::thread::sleep(Duration::from_nanos( (sleep_time * 1_000_000_000.0) as u64 ));
         }
     }
+
+    game.save_chunks(&save).expect("Failed to save world");
 }
 
+/** Наибольшая дальность прорисовки в чанках */
+const MAX_RENDER_DIST: i32 = 32;
+/** Время кадра, которое можно потратить на загрузку готовых чанков и их моделей */
+const CHUNK_UPDATE_BUDGET: Duration = Duration::from_millis(4);
+
+/** Клавиши выбора блока: 1 - первый блок после воздуха и т.д., 0 - десятый, минус и равно - следующие */
+const BLOCK_KEYS: [sdl2::keyboard::Keycode; 12] = {
+    use sdl2::keyboard::Keycode::*;
+    [Num1, Num2, Num3, Num4, Num5, Num6, Num7, Num8, Num9, Num0, Minus, Equals]
+};
+
+/** Границы модели (min, max) */
+type Bounds = (Vec3, Vec3);
+
 struct ModelList<'a> {
-    models: Vec<(bool, &'a Model, Mat4)>, //Model and object matrix
+    models: Vec<(bool, &'a Model, Mat4, Option<Bounds>)>, //Model, object matrix and world space bounds (min, max)
+    visible: usize, //Сколько моделей нарисовал последний render_all
+    culled: usize,  //Сколько моделей он пропустил, так как они вне поля зрения
 }
 impl<'a> ModelList<'a> {
-    pub fn new() -> Self {  Self{ models: vec![] }  }
+    pub fn new() -> Self {  Self{ models: vec![], visible: 0, culled: 0 }  }
     pub fn add_model(&mut self, m: &'a Model) -> usize {
-        self.models.push((true, m, Mat4::new(1.0)));
+        self.models.push((true, m, Mat4::new(1.0), None));
         self.models.len() - 1
     }
     pub fn place_object(&mut self, id: usize, matrix: Mat4) {
         self.models[id].0 = true;
         self.models[id].2 = matrix;
     }
-    pub fn set_renderable(&mut self, id: usize) {
-        self.models[id].0 = true;
+    /** Модель чанка с началом координат origin: размещается и получает границы чанка */
+    pub fn add_chunk(&mut self, m: &'a Model, origin: Vec3) {
+        let id = self.add_model(m);
+        self.place_object(id, Mat4::object_mat(origin.x(), origin.y(), origin.z(), 0.0, 0.0, 0.0, 1.0, 1.0, 1.0));
+        self.set_bounds(id, origin - 0.5, origin + 31.5);
+    }
+    /** Границы размещенной модели в мировых координатах. Модели без границ рисуются всегда */
+    pub fn set_bounds(&mut self, id: usize, min: Vec3, max: Vec3) {
+        self.models[id].3 = Some((min, max));
     }
 
-    pub fn render_all(&mut self, program: &Program, object_uniform_id: usize) {
-        for (is_renderable, model, matrix) in &mut self.models {
-            if *is_renderable {
-                program.uniform_mat4(object_uniform_id, matrix);
-                model.render();
+    /** Нарисовать модели, границы которых попадают в пирамиду видимости (обычно из той же projview, что и в program) */
+    pub fn render_all(&mut self, program: &Program, object_uniform_id: usize, frustum: &Frustum) {
+        self.visible = 0;
+        self.culled = 0;
+        for (is_renderable, model, matrix, bounds) in &mut self.models {
+            if !*is_renderable { continue; }
+            if let Some((min, max)) = bounds {
+                if !frustum.intersects_box(*min, *max) {
+                    self.culled += 1;
+                    continue;
+                }
             }
+            self.visible += 1;
+            program.uniform_mat4(object_uniform_id, matrix);
+            model.render();
         }
     }
 
+    pub fn visible_count(&self) -> usize { self.visible }
+    pub fn culled_count(&self) -> usize { self.culled }
+
     pub fn finish_render(&mut self) {
-        for (is_renderable, _, _) in &mut self.models { *is_renderable = false; }
+        for (is_renderable, _, _, _) in &mut self.models { *is_renderable = false; }
     }
 }
 
@@ -448,7 +617,7 @@ fn gen_framebuffer(width: u32, height: u32) -> (u32, u32, u32, u32, u32) {
 
         // укажем OpenGL, какие буферы мы будем использовать при рендеринге
         let attachments = vec![ gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1, gl::COLOR_ATTACHMENT2, gl::COLOR_ATTACHMENT3, gl::DEPTH_ATTACHMENT ];
-        gl::DrawBuffers(3, attachments.as_ptr());
+        gl::DrawBuffers(4, attachments.as_ptr());
         // После так же добавим буфер глубины и проверку на валидность фреймбуфера.
 
         (g_buffer, g_position, g_normal, g_color, g_light)
@@ -498,64 +667,20 @@ fn load_to_gpu_with_mipmaps(texture: u32, image: &DynamicImage, mipmap_layers: u
     tex_name
 }
 
-fn tmp_create_models(max_dist: i32, game: &game::Game) -> (Vec<(game::Chunk, Model)>, Vec<Model>) {
-    let mut chunks: Vec<(game::Chunk, Model)> = vec![];
-    for cx in 0..max_dist {
-        for cy in 0..max_dist {
-            let mut chunk = game::Chunk::empty(cx, cy, 0);
-            for x in 0..32_i32 {
-                for y in 0..32_i32 {
-                    let h = ((x + cx * 32).pow(2) + (y + cy*32).pow(2)) as f32;
-                    let h = (h * 0.25).sqrt().sin() + 1.0;
-                    let h = (h * 2.5).floor() as i32;
-                    //let h = 5i32;
-                    for z in 0..h {
-                        chunk.set_block(3, x, y, z, DenseBools(63));
-                    }
-                    chunk.set_block(2, x, y, h, DenseBools(63));
-                    if x == 10 && y == 10 {
-                        for dx in -2..3 {
-                            for dy in -2..3 {
-                                for dz in -2..3 {
-                                    if (dx*dx + dy*dy + dz*dz) <= 2 {
-                                        chunk.set_block(6, x + dx, y + dy, h + 8 + dz, DenseBools(63));
-                                    }
-                                }
-                            }
-                        }
-                        for z in (h+1)..(h + 8) {
-                            chunk.set_block(5, x, y, z, DenseBools(0));
-                        }
-                    }
-                }
-            }
-
-            if cx == 0 && cy == 0 {
-                chunk.set_block(5, 20, 20, 30, DenseBools(0));
-            }
-
-            let model = chunk.build_model(game.blocks(), game.models());
-            chunks.push((chunk, model));
-        }
-    }
-
+/** Модели всех блоков для витрины */
+fn tmp_create_models(game: &game::Game) -> Vec<Model> {
     let mut blocks: Vec<Model> = vec![];
     for block in game.blocks() {
         let block = {
-            let mut vertices: Vec<game::Vertex> = vec![];
-            let mut indices: Vec<u32> = vec![];
-
+            let mut mesh = game::ChunkMesh::new();
             let model: &game::BlockModel = &game.models()[block.model_id];
-            model.add_to_model(mat::Vec3::new(0.0, 0.0, 0.0), 0, 0, &mut vertices, &mut indices, &block.textures);
-
-            use game::AttribType::*;
-            let attributes: Vec<game::AttribType> = vec![Vec3, Vec3, Vec3, Vec3, Vec2, Int, Int];
-            game::texture_model(&vertices, &indices, &attributes)
+            model.add_to_model(mat::Vec3::new(0.0, 0.0, 0.0), 0, 0, &mut mesh.vertices, &mut mesh.indices, &block.textures);
+            mesh.upload()
         };
         blocks.push(block);
     }
 
-    (chunks, blocks)
+    blocks
 }
 
 fn tmp_display_model() -> Model {
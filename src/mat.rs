@@ -797,4 +797,69 @@ impl From<Vec4> for Rgba<u8> {
     fn from(item: Vec4) -> Self {
         Rgba::from([item.x().round() as u8, item.y().round() as u8, item.z().round() as u8, item.w().round() as u8])
     }
-}
\ No newline at end of file
+}
+
+/** Пирамида видимости камеры: шесть плоскостей (a, b, c, d), точка p внутри, если a*x + b*y + c*z + d >= 0 для всех.
+ Нормали плоскостей смотрят внутрь */
+#[derive(Copy, Clone, Debug)]
+pub struct Frustum {
+    planes: [Vec4; 6],
+}
+impl Frustum {
+    /** Плоскости из матрицы Projection * View (метод Gribb-Hartmann): строка 3 плюс/минус строки 0, 1, 2.
+     Подходит и для перспективной, и для ортографической проекции */
+    pub fn from_projview(m: &Mat4) -> Frustum {
+        let row = |i: usize| Vec4::new(m.get(i, 0), m.get(i, 1), m.get(i, 2), m.get(i, 3));
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        let add = |a: Vec4, b: Vec4| Vec4::new(a.x + b.x, a.y + b.y, a.z + b.z, a.w + b.w);
+        let sub = |a: Vec4, b: Vec4| Vec4::new(a.x - b.x, a.y - b.y, a.z - b.z, a.w - b.w);
+        Frustum { planes: [add(r3, r0), sub(r3, r0), add(r3, r1), sub(r3, r1), add(r3, r2), sub(r3, r2)] }
+    }
+
+    /** Пересекает ли параллелепипед (по осям, от min до max) пирамиду. Проверка консервативная:
+     у самых углов пирамиды может вернуть true для параллелепипеда снаружи */
+    pub fn intersects_box(&self, min: Vec3, max: Vec3) -> bool {
+        self.planes.iter().all(|p| {
+            //Угол параллелепипеда дальше всего в сторону нормали плоскости
+            let x = if p.x >= 0.0 { max.x() } else { min.x() };
+            let y = if p.y >= 0.0 { max.y() } else { min.y() };
+            let z = if p.z >= 0.0 { max.z() } else { min.z() };
+            p.x * x + p.y * y + p.z * z + p.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /** Куб со стороной 2 с центром в (x, y, z) */
+    fn cube_at(x: f32, y: f32, z: f32) -> (Vec3, Vec3) {
+        let center = Vec3::new(x, y, z);
+        (center - 1.0, center + 1.0)
+    }
+
+    #[test]
+    fn frustum_of_perspective_camera() {
+        //Камера в (0, 0, 0) с углом обзора 90 градусов и дальностью 100. С нулевыми углами смотрит вдоль +y, при повороте на 90 градусов - вдоль +x
+        let projection = Mat4::perspective_mat(std::f32::consts::PI / 2.0, 1.0, 0.05, 100.0);
+        for (horz_ang, forward, side) in [(0.0, Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0)),
+                                          (std::f32::consts::PI / 2.0, Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0))] {
+            let frustum = Frustum::from_projview(&(projection * Mat4::cam_mat(0.0, horz_ang, 0.0, 0.0, 0.0)));
+            let at = |forward_dist: f32, side_dist: f32, up: f32| {
+                let center = forward * forward_dist + side * side_dist;
+                cube_at(center.x(), center.y(), up)
+            };
+            let inside = |(min, max): (Vec3, Vec3)| frustum.intersects_box(min, max);
+
+            assert!(inside(at(10.0, 0.0, 0.0)), "спереди, поворот {}", horz_ang);
+            assert!(inside(at(10.0, 8.0, -8.0)), "спереди у края, поворот {}", horz_ang);
+            assert!(inside(at(0.0, 0.0, 0.0)), "вокруг камеры, поворот {}", horz_ang);
+            assert!(!inside(at(-10.0, 0.0, 0.0)), "сзади, поворот {}", horz_ang);
+            assert!(!inside(at(150.0, 0.0, 0.0)), "дальше дальности, поворот {}", horz_ang);
+            assert!(!inside(at(10.0, 30.0, 0.0)), "сбоку, поворот {}", horz_ang);
+            assert!(!inside(at(10.0, -30.0, 0.0)), "сбоку, поворот {}", horz_ang);
+            assert!(!inside(at(10.0, 0.0, 30.0)), "сверху, поворот {}", horz_ang);
+        }
+    }
+}
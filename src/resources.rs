@@ -50,6 +50,9 @@ impl Resources {
 
         Ok(unsafe { ffi::CString::from_vec_unchecked(buffer) })
     }
+    pub fn load_string(&self, resource_name: &str) -> Result<String, Error> {
+        Ok(fs::read_to_string(self.root_path.join(resource_name))?)
+    }
     pub fn load_png(&self, resource_name: &str) -> Result<DynamicImage, Error> {
         let path = self.root_path.join(resource_name);
         match image::io::Reader::open(path.clone()) {
@@ -100,6 +103,10 @@ impl Resources {
 
     /** Возвращает список всех изображений (локальный путь) в папке, всех подпапках и т.д.*/
     pub fn get_all_images_list(&self, dir: PathBuf) -> Vec<PathBuf> {
+        self.get_all_files_list(dir, FileFormat::Png)
+    }
+    /** Возвращает список всех файлов указанного формата (локальный путь) в папке, всех подпапках и т.д.*/
+    pub fn get_all_files_list(&self, dir: PathBuf, format: FileFormat) -> Vec<PathBuf> {
         let main_path = self.root_path.join(dir);
         let mut files_list: Vec<(PathBuf, FileFormat)> = Vec::new();
         read_directory_to_vec(&main_path, &mut files_list);
@@ -107,13 +114,10 @@ impl Resources {
         let mut result: Vec<PathBuf> = Vec::new();
 
         for (file_path, file_format) in files_list {
-            match file_format {
-                FileFormat::Png => {
-                    let local_path = file_path.strip_prefix(main_path.clone()).unwrap();
-                    result.push(local_path.to_path_buf() );
-                }
-                _ => {}
-            };
+            if file_format == format {
+                let local_path = file_path.strip_prefix(main_path.clone()).unwrap();
+                result.push(local_path.to_path_buf() );
+            }
         }
 
         result
@@ -149,6 +153,7 @@ impl Resources {
 pub enum FileFormat {
     Folder,
     Png,
+    Json,
 
     Frag,
     Vert,
@@ -161,6 +166,7 @@ impl FileFormat {
         match &s.to_lowercase()[..] {
             "" => FileFormat::Folder,
             "png" => FileFormat::Png,
+            "json" => FileFormat::Json,
             "frag" => FileFormat::Frag,
             "vert" => FileFormat::Vert,
             _ => FileFormat::Other,
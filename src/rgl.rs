@@ -252,6 +252,18 @@ impl Model {
             );
         }
     }
+    /** Заменить индексы (например, после сортировки треугольников). Количество индексов не меняется */
+    pub fn update_indices(&self, indices: &[u32]) {
+        assert_eq!(indices.len(), self.indices_count as usize);
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ebo);
+            gl::BufferSubData(gl::ELEMENT_ARRAY_BUFFER, 0,
+                              std::mem::size_of_val(indices) as gl::types::GLsizeiptr,
+                              indices.as_ptr() as *const gl::types::GLvoid);
+            gl::BindVertexArray(0);
+        }
+    }
 }
 impl Model {
     pub fn cube() -> Model {